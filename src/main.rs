@@ -1,186 +1,378 @@
 mod naive;
 #[allow(dead_code)]
-use naive::render::Window;
-use naive::gfx::{triangle, vector, Triangle, Vec3D, Matrix, Mesh};
-use naive::gfx;
-use std::process::exit;
+use naive::render::{Window, Renderer};
+use naive::shadow::{ShadowMap, LightProjection};
+use naive::controls::{OrbitController, MouseLook};
+use naive::gfx::shader::{self, FragmentShader, WorldVertexShader, HeightColor, Toon, NormalView};
+use naive::gfx::{Vec3D, Matrix, Mesh, Light, Camera};
+use naive::gfx::light::LightKind;
+use naive::gfx::shading::Material;
+use naive::gfx::camera::CanonicalView;
+use naive::gfx::fog::{Fog, FogMode};
+use naive::scene::{Pick, Scene, SceneBvh, Transform};
+use naive::scene::file::{self as scene_file, MeshSource, SceneFile, Settings};
+use naive::gfx::bvh::Ray;
+use naive::animation::Animation;
 use sdl2::{
     event::Event,
     keyboard::Keycode,
+    mouse::MouseButton,
     pixels::Color
 };
-use std::cmp::max;
-use crate::naive::gfx::vector::vec_mul_by;
-use std::collections::LinkedList;
+use std::sync::Arc;
 
 const C_WHITE: Color = Color::RGBA(255,255,255,255);
-const C_BLACK: Color = Color::RGBA(0,0,0,255);
-const W_WIDTH:   u32 = 640;
-const W_HEIGHT:  u32 = 480;
+const C_SKY:   Color = Color::RGBA(150,170,190,255);
+const C_PICK:  Color = Color::RGBA(255,220,0,255);
+const SHADOW_MAP_SIZE: u32 = 1024;
+
+/// Custom shader effects, cycled at runtime
+#[derive(Copy, Clone, Debug)]
+enum Effect {
+    None,
+    Height,
+    Toon,
+    Normals,
+}
+
+impl Effect {
+    fn next(&self) -> Effect {
+        match self {
+            Effect::None => Effect::Height,
+            Effect::Height => Effect::Toon,
+            Effect::Toon => Effect::Normals,
+            Effect::Normals => Effect::None,
+        }
+    }
+}
+
+/// Kind of the light casting the shadow map, for the title
+fn shadow_name(shadow: &Option<ShadowMap>) -> &'static str {
+    match shadow.as_ref().map(|s| s.light) {
+        None => "off",
+        Some(LightProjection::Directional { .. }) => "directional",
+        Some(LightProjection::Spot { .. }) => "spot",
+    }
+}
+
+/// Direction towards the first directional light, the shadows and the toon effect follow it
+fn sun_direction(lights: &[Light]) -> Vec3D {
+    lights.iter()
+        .find_map(|l| match l.kind { LightKind::Directional { direction } => Some(direction), _ => None })
+        .unwrap_or(Vec3D::new(0.0, 1.0, 0.0))
+}
+
+/// A scene of one mesh in front of the camera, with the default settings
+fn single_mesh(path: &str) -> Result<SceneFile, String> {
+    let mut scene = Scene::new();
+    let mesh = scene.add_mesh(Mesh::load_object(path)?);
+    scene.add_node("mesh", None, Transform::from_translation(0.0, 0.0, 10.0), Some(mesh));
+    let settings = Settings::default();
+    let ratio = settings.height as f32 / settings.width as f32;
+    Ok(SceneFile {
+        scene,
+        sources: vec![MeshSource { name: "mesh".to_string(), path: path.to_string() }],
+        camera: Camera::new(Vec3D::init(), 90.0, ratio, 0.1, 1000.0),
+        lights: Vec::new(),
+        settings,
+        animation: Animation::new()
+    })
+}
+
+/// `dir/name.scene` is saved as `dir/name.saved.scene`
+fn saved_path(path: &str) -> String {
+    let path = std::path::Path::new(path);
+    let stem = path.file_stem().map_or("scene".into(), |s| s.to_string_lossy());
+    path.with_file_name(format!("{}.saved.scene", stem)).to_string_lossy().into_owned()
+}
 
 fn create() {
-    let mut window = Window::new(W_WIDTH, W_HEIGHT);
-    let mut mesh = Mesh::load_object("example_objs/landscape.obj");
+    // a scene file, or a single OBJ placed in front of the camera
+    let path = std::env::args().nth(1).unwrap_or_else(|| "example_objs/demo.scene".to_string());
+    let loaded = if path.ends_with(".obj") {
+        single_mesh(&path)
+    } else {
+        scene_file::load(&path).map_err(|e| e.to_string())
+    };
+    let SceneFile { mut scene, sources, mut camera, lights, settings, mut animation } = match loaded {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        },
+    };
+    let (width, height) = (settings.width, settings.height);
+    let mut window = Window::new(width, height);
+    let mut renderer = Renderer::new(width, height, settings.anti_aliasing);
+    // without lights in the file the renderer keeps its default sun
+    if !lights.is_empty() {
+        renderer.lights = lights;
+    }
+    if !settings.apply(&mut renderer) {
+        eprintln!("{}: no light casts the shadows", path);
+    }
+    let mut effect = Effect::None;
+    // P adds a point and a spot light after the ones of the scene, this is where they start
+    let mut added_lights: Option<usize> = None;
 
-    let mut f_theta: f32 = 0.0;
-    let near  = 0.1;
-    let far   = 1000.0;
-    let fov   = 90.0;
-    let ratio = W_HEIGHT as f32/ W_WIDTH as f32;
+    scene.update();
+    let mut bvh = SceneBvh::new(&scene);
+    let mut picked: Option<Pick> = None;
 
-    let mut m = Matrix::init_projection(fov, ratio, near, far);
+    let far_fog = 150.0;
+    // free-fly when there's no orbit controller
+    let mut orbit: Option<OrbitController> = None;
+    let mut mouse_look = MouseLook::new();
+    let mut looking = false;
 
     let mut event = window.create_event_pump();
     let mut timer = std::time::Instant::now();
     let mut draw_timer = std::time::Instant::now();
     let mut fps = 0;
-    let mut yaw = 0.0;
-
-    let mut camera = Vec3D::init();
-    let mut look_d = Vec3D::new(0.0, 0.0, 1.0);
 
     'run: loop {
-        // f_theta += 0.01;
         let elapsed_time = draw_timer.elapsed().as_micros() as f32 / 1e5;
         draw_timer = std::time::Instant::now();
-        // window.draw_bg(Color::RGB(54,54,54));
-        window.draw_bg(C_BLACK);
-        let forward = vec_mul_by(&look_d, 8.0 * elapsed_time);
+        // elapsed_time counts tenths of a second
+        animation.advance(elapsed_time / 10.0);
+        animation.apply(&mut scene, &mut camera, &mut renderer.lights);
+        scene.update();
+        bvh.update(&scene);
+
         for e in event.poll_iter() {
+            if let Some(orbit) = orbit.as_mut() {
+                if orbit.handle_event(&e) {
+                    continue
+                }
+            } else if looking && mouse_look.handle_event(&e, &mut camera) {
+                continue
+            }
             match e {
                 Event::Quit {..} |
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'run,
+                // F5 saves the current state next to the loaded file
+                Event::KeyDown { keycode: Some(Keycode::F5), .. } => {
+                    let target = saved_path(&path);
+                    match scene_file::save(&target, &scene, &sources, &camera, &renderer, &animation) {
+                        Ok(()) => println!("saved {}", target),
+                        Err(e) => eprintln!("can't save {}: {}", target, e),
+                    }
+                },
+                // Animation: play/pause, scrub a second back/forward, rewind, looping
+                Event::KeyDown { keycode: Some(Keycode::Space), .. } => animation.toggle(),
+                Event::KeyDown { keycode: Some(Keycode::Comma), .. } => animation.seek(animation.time() - 1.0),
+                Event::KeyDown { keycode: Some(Keycode::Period), .. } => animation.seek(animation.time() + 1.0),
+                Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => animation.seek(0.0),
+                Event::KeyDown { keycode: Some(Keycode::N), .. } => animation.looping = !animation.looping,
+                // Picking: a left click selects the triangle under the cursor, the screen center with mouse-look
+                Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } => {
+                    let (x, y) = if looking { (width as i32 / 2, height as i32 / 2) } else { (x, y) };
+                    picked = bvh.pick(&camera, x as f32, y as f32, width as f32, height as f32);
+                },
                 // Tilting
-                Event::KeyDown { keycode: Some(Keycode::Up), .. } => camera.y += 10.0 * elapsed_time,
-                Event::KeyDown { keycode: Some(Keycode::Down), .. } => camera.y -= 10.0 * elapsed_time,
-                Event::KeyDown { keycode: Some(Keycode::Left), .. } => camera.x += 10.0 * elapsed_time,
-                Event::KeyDown { keycode: Some(Keycode::Right), .. } => camera.x -= 10.0 * elapsed_time,
+                Event::KeyDown { keycode: Some(Keycode::Up), .. } => camera.pitch(1.5 * elapsed_time),
+                Event::KeyDown { keycode: Some(Keycode::Down), .. } => camera.pitch(-1.5 * elapsed_time),
                 // Movement
-                Event::KeyDown { keycode: Some(Keycode::W), .. } => camera += forward,
-                Event::KeyDown { keycode: Some(Keycode::S), .. } => camera -= forward,
-                Event::KeyDown { keycode: Some(Keycode::A), .. } => yaw -= 3.0 * elapsed_time,
-                Event::KeyDown { keycode: Some(Keycode::D), .. } => yaw += 3.0 * elapsed_time,
+                Event::KeyDown { keycode: Some(Keycode::Left), .. } => camera.strafe(-10.0 * elapsed_time),
+                Event::KeyDown { keycode: Some(Keycode::Right), .. } => camera.strafe(10.0 * elapsed_time),
+                Event::KeyDown { keycode: Some(Keycode::PageUp), .. } => camera.position.y += 10.0 * elapsed_time,
+                Event::KeyDown { keycode: Some(Keycode::PageDown), .. } => camera.position.y -= 10.0 * elapsed_time,
+                Event::KeyDown { keycode: Some(Keycode::W), .. } => camera.move_forward(8.0 * elapsed_time),
+                Event::KeyDown { keycode: Some(Keycode::S), .. } => camera.move_forward(-8.0 * elapsed_time),
+                Event::KeyDown { keycode: Some(Keycode::A), .. } => camera.yaw(-3.0 * elapsed_time),
+                Event::KeyDown { keycode: Some(Keycode::D), .. } => camera.yaw(3.0 * elapsed_time),
+                // Mouse-look: R captures the mouse, I inverts Y, K enables rolling with Q/E, -/= sensitivity
+                Event::KeyDown { keycode: Some(Keycode::R), .. } if orbit.is_none() => {
+                    looking = !looking;
+                    window.set_relative_mouse_mode(looking);
+                },
+                Event::KeyDown { keycode: Some(Keycode::I), .. } => mouse_look.invert_y = !mouse_look.invert_y,
+                Event::KeyDown { keycode: Some(Keycode::K), .. } => {
+                    mouse_look.roll = !mouse_look.roll;
+                    if !mouse_look.roll {
+                        camera.roll = 0.0;
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::Minus), .. } => mouse_look.sensitivity *= 0.8,
+                Event::KeyDown { keycode: Some(Keycode::Equals), .. } => mouse_look.sensitivity *= 1.25,
+                // Camera modes: C switches between free-fly and orbit, Z focuses the orbit on the picked node
+                // or, with nothing picked, the whole scene
+                Event::KeyDown { keycode: Some(Keycode::C), .. } => {
+                    orbit = match orbit {
+                        Some(_) => None,
+                        None => Some(OrbitController::from_camera(&camera, 50.0)),
+                    };
+                    looking = false;
+                    window.set_relative_mouse_mode(false);
+                },
+                Event::KeyDown { keycode: Some(Keycode::Z), .. } => {
+                    looking = false;
+                    window.set_relative_mouse_mode(false);
+                    let controller = orbit.get_or_insert_with(|| OrbitController::from_camera(&camera, 50.0));
+                    let (center, radius) = picked.and_then(|p| scene.drawables().find(|&(i, _, _)| i == p.node))
+                        .map_or_else(|| scene.bounding_sphere(), |(_, mesh, world)| mesh.bounding_sphere(world));
+                    controller.focus_sphere(&center, radius, &camera);
+                },
+                // Projection: 0 toggles perspective/orthographic, 1, 2 and 3 are the front, top and side views
+                Event::KeyDown { keycode: Some(Keycode::Num0), .. } => {
+                    let focus = match &orbit {
+                        Some(orbit) => orbit.distance,
+                        None => (scene.bounding_sphere().0 - camera.position).len(),
+                    };
+                    camera.toggle_projection(focus);
+                },
+                Event::KeyDown { keycode: Some(key @ Keycode::Num1), .. } |
+                Event::KeyDown { keycode: Some(key @ Keycode::Num2), .. } |
+                Event::KeyDown { keycode: Some(key @ Keycode::Num3), .. } => {
+                    let view = match key {
+                        Keycode::Num1 => CanonicalView::Front,
+                        Keycode::Num2 => CanonicalView::Top,
+                        _ => CanonicalView::Side,
+                    };
+                    match orbit.as_mut() {
+                        Some(orbit) => {
+                            let (yaw, pitch) = view.angles();
+                            orbit.yaw = yaw;
+                            orbit.pitch = pitch;
+                        },
+                        None => {
+                            let (center, radius) = scene.bounding_sphere();
+                            let distance = radius / (camera.fov * 0.5).to_radians().sin();
+                            camera.look_from(view, &center, distance);
+                            camera.ortho_size = radius;
+                        },
+                    }
+                },
+                // Shading
+                Event::KeyDown { keycode: Some(Keycode::G), .. } => renderer.shading = renderer.shading.next(),
+                Event::KeyDown { keycode: Some(Keycode::T), .. } => renderer.tiled = !renderer.tiled,
+                Event::KeyDown { keycode: Some(Keycode::U), .. } => renderer.frustum_culling = !renderer.frustum_culling,
+                Event::KeyDown { keycode: Some(Keycode::F), .. } => effect = effect.next(),
+                // Fog: off -> linear -> exponential -> exponential squared -> off
+                Event::KeyDown { keycode: Some(Keycode::O), .. } => {
+                    let (start, end) = (0.5 * far_fog, far_fog);
+                    renderer.fog = match renderer.fog {
+                        None => Some(Fog::new(FogMode::Linear, C_SKY, start, end)),
+                        Some(fog) if fog.mode == FogMode::ExponentialSquared => None,
+                        Some(fog) => Some(Fog::new(fog.mode.next(), C_SKY, start, end)),
+                    };
+                    renderer.set_background(if renderer.fog.is_some() { C_SKY } else { settings.background });
+                },
+                // Shadows
+                Event::KeyDown { keycode: Some(Keycode::H), .. } => {
+                    let was_off = renderer.shadow.take().is_none();
+                    if was_off && !renderer.enable_shadows(SHADOW_MAP_SIZE, 1) {
+                        println!("no directional or spot light casts shadows");
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::J), .. } => {
+                    if let Some(shadow) = renderer.shadow.as_mut() {
+                        shadow.pcf = (shadow.pcf + 1) % 4;
+                    }
+                },
+                // Culling
+                Event::KeyDown { keycode: Some(Keycode::B), .. } => {
+                    let first = scene.meshes.first().map_or(Material::default(), |m| m.materials[0]);
+                    let (cull, winding) = (first.cull.next(), first.winding);
+                    for mesh in scene.meshes.iter_mut() {
+                        mesh.set_culling(cull, winding);
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::V), .. } => {
+                    let first = scene.meshes.first().map_or(Material::default(), |m| m.materials[0]);
+                    let (cull, winding) = (first.cull, first.winding.flipped());
+                    for mesh in scene.meshes.iter_mut() {
+                        mesh.set_culling(cull, winding);
+                    }
+                },
+                // Render modes
+                Event::KeyDown { keycode: Some(Keycode::M), .. } => renderer.render_mode = renderer.render_mode.next(),
+                Event::KeyDown { keycode: Some(Keycode::LeftBracket), .. } => {
+                    renderer.wire_style.thickness = (renderer.wire_style.thickness - 1).max(1)
+                },
+                Event::KeyDown { keycode: Some(Keycode::RightBracket), .. } => renderer.wire_style.thickness += 1,
+                // Lights
+                Event::KeyDown { keycode: Some(Keycode::L), .. } => {
+                    let rotation = Matrix::init_rotation_y(0.5 * elapsed_time);
+                    for light in renderer.lights.iter_mut() {
+                        if let LightKind::Directional { direction } = &mut light.kind {
+                            *direction = rotation.apply(direction);
+                        }
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::P), .. } => match added_lights.take() {
+                    Some(first) => renderer.lights.truncate(first),
+                    None => {
+                        added_lights = Some(renderer.lights.len());
+                        renderer.lights.push(Light::point(Vec3D::new(0.0, 40.0, 10.0), 120.0, Color::RGB(255, 160, 60), 1.5));
+                        renderer.lights.push(Light::spot(Vec3D::new(-40.0, 60.0, 10.0), Vec3D::new(0.3, -1.0, 0.0),
+                                                         15.0, 30.0, 150.0, Color::RGB(80, 140, 255), 2.0));
+                    },
+                },
                 _ => {}
             }
         }
-
-        let mut raster_triangles = Vec::new();
-
-        let mat_rz = Matrix::init_rotation_z(f_theta);
-        let mat_rx = Matrix::init_rotation_x(f_theta);
-        let mat_ry = Matrix::init_rotation_y(f_theta);
-        let mat_trans = Matrix::init_translation(0.0, 0.0, 10.0);
-        let mut mat_world = Matrix::init_identity();
-        mat_world = mat_rz * mat_rx * mat_ry; // apply rotation to the world
-        mat_world = mat_world * mat_trans; // apply translation to the world
-
-        let mut target = Vec3D::new(0.0, 0.0, 1.0);
-        let up_vec = Vec3D::new(0.0, -1.0, 0.0);
-        let mat_crot = Matrix::init_rotation_y(yaw);
-        look_d = mat_crot.apply(&target);
-        target = camera + look_d;
-        let mat_view = Matrix::point_at(&camera, &target, &up_vec).quick_inverse();
-
-
-        // Triangles
-        for tri in mesh.tris.iter() {
-
-            let t_transformed = Triangle::from_matrix_application(&mat_world, tri);
-
-            let line1 = t_transformed.p[1] - t_transformed.p[0];
-            let line2 = t_transformed.p[2] - t_transformed.p[0];
-            let mut normal = gfx::vector::cross_product(&line1, &line2);
-
-            // a ray from triangle to camera
-            let camera_ray = t_transformed.p[0] - camera;
-
-            if normal.normalize().dot_product(&camera_ray) < 0.0 {
-                let mut illumination = Vec3D::new(0.0, 1.0, -1.0); // Facing camera
-                let dp = illumination.normalize().dot_product(&normal).max(0.1);
-
-                // apply view matrix
-                let t_viewed = Triangle::from_matrix_application(&mat_view, &t_transformed);
-
-                // check clipping
-                let clipped = triangle::clipping(&Vec3D::new(0.0, 0.0, 0.1),
-                                                 &mut Vec3D::new(0.0, 0.0, 1.0), &t_viewed);
-                for clip in clipped.iter() {
-                    if clip.is_none() {continue}
-                    let t_clipped = clip.unwrap();
-
-                    // 3D -> 2D
-                    let mut projection = Triangle::from_matrix_application(&m, &t_clipped);
-                    projection.normalize();
-                    projection.shade(dp);
-
-                    // Scale
-                    projection.add_each_point(Vec3D::new(1.0, 1.0, 0.0));
-
-                    projection.p[0].x *= 0.5 * W_WIDTH as f32;
-                    projection.p[0].y *= 0.5 * W_HEIGHT as f32;
-                    projection.p[1].x *= 0.5 * W_WIDTH as f32;
-                    projection.p[1].y *= 0.5 * W_HEIGHT as f32;
-                    projection.p[2].x *= 0.5 * W_WIDTH as f32;
-                    projection.p[2].y *= 0.5 * W_HEIGHT as f32;
-
-                    raster_triangles.push(projection);
-                }
-            }
+        if let Some(orbit) = &orbit {
+            orbit.apply(&mut camera);
         }
 
+        // Custom effects replace the built-in shading with a vertex and a fragment shader
+        let fragment_shader: Option<Arc<dyn FragmentShader>> = match effect {
+            Effect::None => None,
+            Effect::Height => {
+                let (min_y, max_y) = scene.drawables()
+                    .map(|(_, mesh, world)| shader::height_range(mesh, world))
+                    .fold((f32::MAX, f32::MIN), |(lo, hi), (min, max)| (lo.min(min), hi.max(max)));
+                Some(Arc::new(HeightColor {
+                    min: min_y, max: max_y, low: Color::RGB(20, 90, 30), high: Color::RGB(240, 240, 230)
+                }))
+            },
+            Effect::Toon => Some(Arc::new(Toon {
+                light_dir: sun_direction(&renderer.lights), bands: 4, color: Color::RGB(230, 160, 60)
+            })),
+            Effect::Normals => Some(Arc::new(NormalView)),
+        };
+
+        // the shadow map follows the light and covers the whole scene
+        if renderer.shadow.is_some() {
+            let (center, radius) = scene.bounding_sphere();
+            renderer.aim_shadow(center, radius);
+        }
 
-        raster_triangles.sort_by(|&t1, &t2| {
-            let z1 = (t1.p[0].z + t1.p[1].z + t1.p[2].z)/3.0;
-            let z2 = (t2.p[0].z + t2.p[1].z + t2.p[2].z)/3.0;
-            z2.partial_cmp(&z1).unwrap()
-        });
-
-        // CLIP AGAINST THE SCREEN
-        for tri_raster in &raster_triangles {
-            let mut alist = LinkedList::new();
-            let mut count = 1;
-            alist.push_back(*tri_raster);
-
-            for p in 0..4 {
-                while count > 0 {
-                    let test: Triangle = alist.pop_front().unwrap();
-                    count -= 1;
-
-                    let clipped = match p {
-                        0 => triangle::clipping(&Vec3D::new(0.0,0.0,0.0),
-                                                &mut Vec3D::new(0.0,1.0,0.0), &test),
-
-                        1 => triangle::clipping(&Vec3D::new(0.0, W_HEIGHT as f32 - 1.0, 0.0),
-                                                &mut Vec3D::new(0.0,-1.0,0.0), &test),
-
-                        2 => triangle::clipping(&Vec3D::new(0.0,0.0,0.0),
-                                                &mut Vec3D::new(1.0,0.0,0.0), &test),
-
-                        3 => triangle::clipping(&Vec3D::new(W_WIDTH as f32 - 1.0,0.0,0.0),
-                                                &mut Vec3D::new(-1.0,0.0,0.0), &test),
-                        _ => exit(4)
-                    };
-
-                    for clip in &clipped {
-                        if clip.is_some() {alist.push_back(clip.unwrap())}
+        renderer.begin_frame();
+        scene.cast_shadows(&mut renderer);
+        match fragment_shader {
+            Some(fragment) => {
+                let view_projection = camera.view_projection();
+                for (_, mesh, world) in scene.drawables() {
+                    if renderer.cull_object(mesh, world, &camera) {
+                        continue
                     }
+                    let vertex = WorldVertexShader::new(world, &view_projection);
+                    renderer.draw_with(mesh, &vertex, fragment.clone());
                 }
-                count = alist.len();
-            }
-
-            // DRAW
-            for tri in &alist {
-                tri.draw_fast(&mut window, true);
-                // let points = tri.get_2d_points();
-                // window.draw_triangle(C_BLACK, points[0], points[1], points[2]).unwrap(); // wireframe
-            }
-
+            },
+            None => scene.draw(&mut renderer, &camera),
         }
-
-
+        if let Some(triangle) = picked.and_then(|p| scene.world_triangle(p.node, p.triangle)) {
+            renderer.highlight(&triangle, &camera, C_PICK);
+        }
+        renderer.end_frame();
+        window.draw_buffer(&renderer.buffer).unwrap();
 
         if timer.elapsed().as_secs() > 1 {
-            window.set_title(&format!("NAIVE WINDOW. FPS: {}, E = {}", fps, elapsed_time));
+            // what's straight ahead and how close the nearest surface is
+            let target = bvh.intersect_ray(&Ray::new(camera.position, camera.forward()), camera.far)
+                .map_or("-", |(node, _)| scene.nodes[node].name.as_str());
+            let clearance = bvh.nearest_point(&camera.position, camera.far)
+                .map_or(format!("{:.1}", camera.far), |(node, p)| format!("{:.1} to {} #{}", p.distance, scene.nodes[node].name, p.triangle));
+            let pick = picked.map_or("-".to_string(), |p| format!("{} #{} at ({:.1}, {:.1}, {:.1}), normal ({:.2}, {:.2}, {:.2}), {:.1} away",
+                                                                  scene.nodes[p.node].name, p.triangle, p.point.x, p.point.y, p.point.z,
+                                                                  p.normal.x, p.normal.y, p.normal.z, p.distance));
+            window.set_title(&format!("NAIVE WINDOW. FPS: {}, E = {}, tris: {}, culled objects: {}, target: {}, clearance: {}, picked: {}, animation: {:.1}/{:.1}s{}, orbit: {}, {:?}, {:?}, {:?}, effect: {:?}, tiled: {}, shadows: {}, fog: {:?}",
+                                     fps, elapsed_time, renderer.queued(), renderer.culled(), target, clearance, pick,
+                                     animation.time(), animation.duration(), if animation.playing { "" } else { " paused" }, orbit.is_some(), camera.projection, renderer.shading, renderer.render_mode, effect,
+                                     renderer.tiled, shadow_name(&renderer.shadow), renderer.fog.map(|f| f.mode)));
             timer = std::time::Instant::now();
             fps = 0;
         }
@@ -1,3 +1,9 @@
 pub mod text;
 pub mod render;
 pub mod gfx;
+pub mod raster;
+pub mod tiles;
+pub mod shadow;
+pub mod controls;
+pub mod scene;
+pub mod animation;
@@ -0,0 +1,441 @@
+use sdl2::pixels::Color;
+use super::gfx::{Camera, Light, Quaternion, Vec3D};
+use super::gfx::light::LightKind;
+use super::scene::Scene;
+
+/// How a track gets from one key to the next
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation {
+    /// Holds the value of a key until the next one
+    Step,
+    /// Straight line, slerp for rotations
+    Linear,
+    /// Catmull-Rom spline through the keys, smooth at every key
+    Cubic,
+}
+
+/// Values a track can interpolate
+pub trait Animatable: Copy {
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self;
+    /// Catmull-Rom spline between `b` (t = 0) and `c` (t = 1), `a` and `d` are the neighbouring keys
+    fn cubic(a: &Self, b: &Self, c: &Self, d: &Self, t: f32) -> Self;
+}
+
+impl Animatable for f32 {
+    fn lerp(a: &f32, b: &f32, t: f32) -> f32 {
+        a + (b - a) * t
+    }
+
+    fn cubic(a: &f32, b: &f32, c: &f32, d: &f32, t: f32) -> f32 {
+        let (t2, t3) = (t * t, t * t * t);
+        0.5 * (2.0 * b + (c - a) * t + (2.0 * a - 5.0 * b + 4.0 * c - d) * t2 + (3.0 * b - a - 3.0 * c + d) * t3)
+    }
+}
+
+impl Animatable for Vec3D {
+    fn lerp(a: &Vec3D, b: &Vec3D, t: f32) -> Vec3D {
+        Vec3D::new(f32::lerp(&a.x, &b.x, t), f32::lerp(&a.y, &b.y, t), f32::lerp(&a.z, &b.z, t))
+    }
+
+    fn cubic(a: &Vec3D, b: &Vec3D, c: &Vec3D, d: &Vec3D, t: f32) -> Vec3D {
+        Vec3D::new(
+            f32::cubic(&a.x, &b.x, &c.x, &d.x, t),
+            f32::cubic(&a.y, &b.y, &c.y, &d.y, t),
+            f32::cubic(&a.z, &b.z, &c.z, &d.z, t)
+        )
+    }
+}
+
+impl Animatable for Quaternion {
+    fn lerp(a: &Quaternion, b: &Quaternion, t: f32) -> Quaternion {
+        a.slerp(b, t)
+    }
+
+    /// The spline runs over the components, with every key flipped into the hemisphere of `b`
+    /// so it follows the shorter arcs, and gets normalized back to a rotation
+    fn cubic(a: &Quaternion, b: &Quaternion, c: &Quaternion, d: &Quaternion, t: f32) -> Quaternion {
+        let align = |q: &Quaternion, to: &Quaternion| if q.dot(to) < 0.0 { q.negated() } else { *q };
+        let c = align(c, b);
+        let (a, d) = (align(a, b), align(d, &c));
+        Quaternion {
+            w: f32::cubic(&a.w, &b.w, &c.w, &d.w, t),
+            x: f32::cubic(&a.x, &b.x, &c.x, &d.x, t),
+            y: f32::cubic(&a.y, &b.y, &c.y, &d.y, t),
+            z: f32::cubic(&a.z, &b.z, &c.z, &d.z, t)
+        }.normalized()
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Keyframe<T> {
+    /// Seconds from the start of the animation
+    pub time: f32,
+    pub value: T
+}
+
+/// Keys of one value, sorted by time. Before the first key and after the last one the track
+/// holds their values.
+#[derive(Clone, Debug)]
+pub struct Track<T> {
+    pub keys: Vec<Keyframe<T>>,
+    pub interpolation: Interpolation
+}
+
+impl<T: Animatable> Track<T> {
+    pub fn new(interpolation: Interpolation) -> Self {
+        Track { keys: Vec::new(), interpolation }
+    }
+
+    /// Adds a key, keeping the keys sorted. A key at the time of an existing one replaces it.
+    pub fn insert(&mut self, time: f32, value: T) {
+        match self.keys.iter().position(|k| k.time >= time) {
+            Some(i) if self.keys[i].time == time => self.keys[i].value = value,
+            Some(i) => self.keys.insert(i, Keyframe { time, value }),
+            None => self.keys.push(Keyframe { time, value }),
+        }
+    }
+
+    /// Time of the last key
+    pub fn end(&self) -> f32 {
+        self.keys.last().map_or(0.0, |k| k.time)
+    }
+
+    /// Value at the time, `None` for a track without keys
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let (first, last) = (self.keys.first()?, self.keys.last()?);
+        if time <= first.time {
+            return Some(first.value)
+        }
+        if time >= last.time {
+            return Some(last.value)
+        }
+        // the segment [i, i + 1] contains the time
+        let i = self.keys.iter().rposition(|k| k.time <= time)?;
+        let (k1, k2) = (&self.keys[i], &self.keys[i + 1]);
+        let t = (time - k1.time) / (k2.time - k1.time);
+        Some(match self.interpolation {
+            Interpolation::Step => k1.value,
+            Interpolation::Linear => T::lerp(&k1.value, &k2.value, t),
+            Interpolation::Cubic => {
+                let k0 = &self.keys[i.saturating_sub(1)];
+                let k3 = &self.keys[(i + 2).min(self.keys.len() - 1)];
+                T::cubic(&k0.value, &k1.value, &k2.value, &k3.value, t)
+            },
+        })
+    }
+}
+
+/// What a track drives. Nodes are indices into `Scene::nodes`, lights into the light list.
+/// Camera angles are in radians, the field of view in degrees like `Camera::fov`,
+/// light colours are `r g b` vectors in 0..255.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Target {
+    NodeTranslation(usize),
+    NodeRotation(usize),
+    NodeScale(usize),
+    CameraPosition,
+    CameraYaw,
+    CameraPitch,
+    CameraRoll,
+    CameraFov,
+    LightPosition(usize),
+    LightDirection(usize),
+    LightColor(usize),
+    LightIntensity(usize),
+}
+
+/// A track with the type of values its target takes
+#[derive(Clone, Debug)]
+pub enum Channel {
+    Scalar(Track<f32>),
+    Vector(Track<Vec3D>),
+    Rotation(Track<Quaternion>),
+}
+
+impl Channel {
+    /// An empty track of the kind the target takes
+    pub fn for_target(target: Target, interpolation: Interpolation) -> Channel {
+        match target {
+            Target::NodeRotation(_) => Channel::Rotation(Track::new(interpolation)),
+            Target::NodeTranslation(_) | Target::NodeScale(_) | Target::CameraPosition |
+            Target::LightPosition(_) | Target::LightDirection(_) | Target::LightColor(_) => Channel::Vector(Track::new(interpolation)),
+            _ => Channel::Scalar(Track::new(interpolation)),
+        }
+    }
+
+    pub fn interpolation(&self) -> Interpolation {
+        match self {
+            Channel::Scalar(t) => t.interpolation,
+            Channel::Vector(t) => t.interpolation,
+            Channel::Rotation(t) => t.interpolation,
+        }
+    }
+
+    pub fn end(&self) -> f32 {
+        match self {
+            Channel::Scalar(t) => t.end(),
+            Channel::Vector(t) => t.end(),
+            Channel::Rotation(t) => t.end(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Channel::Scalar(t) => t.keys.is_empty(),
+            Channel::Vector(t) => t.keys.is_empty(),
+            Channel::Rotation(t) => t.keys.is_empty(),
+        }
+    }
+}
+
+/// Keyframe tracks played over time.
+/// `advance` moves the playback by the frame time when playing, `seek` jumps to a time
+/// (scrubbing), then `apply` writes the sampled values into the scene, the camera and the lights.
+/// The animation lasts until the last key of its tracks and starts over when `looping`.
+pub struct Animation {
+    pub tracks: Vec<(Target, Channel)>,
+    pub playing: bool,
+    pub looping: bool,
+    /// Playback rate, 1.0 is real time
+    pub speed: f32,
+    time: f32,
+    /// Whether the time moved since the last `apply`
+    changed: bool
+}
+
+impl Animation {
+    pub fn new() -> Self {
+        Animation { tracks: Vec::new(), playing: true, looping: true, speed: 1.0, time: 0.0, changed: true }
+    }
+
+    pub fn add(&mut self, target: Target, channel: Channel) {
+        self.tracks.push((target, channel));
+        self.changed = true;
+    }
+
+    /// Length in seconds: the time of the last key
+    pub fn duration(&self) -> f32 {
+        self.tracks.iter().map(|(_, c)| c.end()).fold(0.0, f32::max)
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn play(&mut self) {
+        if !self.looping && self.time >= self.duration() {
+            self.seek(0.0);
+        }
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn toggle(&mut self) {
+        if self.playing { self.pause() } else { self.play() }
+    }
+
+    /// Jumps to a time, wrapped into the animation when looping, clamped otherwise
+    pub fn seek(&mut self, time: f32) {
+        let duration = self.duration();
+        self.time = if duration <= 0.0 {
+            0.0
+        } else if self.looping {
+            time.rem_euclid(duration)
+        } else {
+            time.max(0.0).min(duration)
+        };
+        self.changed = true;
+    }
+
+    /// Moves the playback by `seconds` of real time, stops at the end unless looping
+    pub fn advance(&mut self, seconds: f32) {
+        if !self.playing || self.tracks.is_empty() {
+            return
+        }
+        self.seek(self.time + seconds * self.speed);
+        if !self.looping && self.time >= self.duration() {
+            self.playing = false;
+        }
+    }
+
+    /// Writes the values at the current time into their targets. Does nothing while the time
+    /// stands still, so the camera and the scene stay free to be moved when paused.
+    /// Targets missing from the scene or the light list are skipped.
+    pub fn apply(&mut self, scene: &mut Scene, camera: &mut Camera, lights: &mut [Light]) {
+        if !self.changed {
+            return
+        }
+        self.changed = false;
+        let time = self.time;
+        for (target, channel) in &self.tracks {
+            match (*target, channel) {
+                (Target::NodeRotation(node), Channel::Rotation(track)) if node < scene.nodes.len() => {
+                    if let Some(q) = track.sample(time) {
+                        scene.transform_mut(node).rotation = q;
+                    }
+                },
+                (target, Channel::Vector(track)) => {
+                    if let Some(v) = track.sample(time) {
+                        apply_vector(target, v, scene, camera, lights);
+                    }
+                },
+                (target, Channel::Scalar(track)) => {
+                    if let Some(v) = track.sample(time) {
+                        apply_scalar(target, v, camera, lights);
+                    }
+                },
+                _ => (),
+            }
+        }
+    }
+}
+
+fn apply_vector(target: Target, v: Vec3D, scene: &mut Scene, camera: &mut Camera, lights: &mut [Light]) {
+    match target {
+        Target::NodeTranslation(node) if node < scene.nodes.len() => scene.transform_mut(node).translation = v,
+        Target::NodeScale(node) if node < scene.nodes.len() => scene.transform_mut(node).scale = v,
+        Target::CameraPosition => camera.position = v,
+        Target::LightPosition(i) => match lights.get_mut(i).map(|l| &mut l.kind) {
+            Some(LightKind::Point { position, .. }) | Some(LightKind::Spot { position, .. }) => *position = v,
+            _ => (),
+        },
+        Target::LightDirection(i) => match lights.get_mut(i).map(|l| &mut l.kind) {
+            Some(LightKind::Directional { direction }) | Some(LightKind::Spot { direction, .. }) if v.len() > 0.0 => {
+                *direction = v;
+                direction.normalize();
+            },
+            _ => (),
+        },
+        Target::LightColor(i) => if let Some(light) = lights.get_mut(i) {
+            let c = |v: f32| v.clamp(0.0, 255.0).round() as u8;
+            light.color = Color::RGBA(c(v.x), c(v.y), c(v.z), light.color.a);
+        },
+        _ => (),
+    }
+}
+
+fn apply_scalar(target: Target, v: f32, camera: &mut Camera, lights: &mut [Light]) {
+    match target {
+        Target::CameraYaw => camera.yaw = v,
+        Target::CameraPitch => {
+            camera.pitch = 0.0;
+            camera.pitch(v);
+        },
+        Target::CameraRoll => camera.roll = v,
+        Target::CameraFov => camera.fov = v.clamp(1.0, 179.0),
+        Target::LightIntensity(i) => if let Some(light) = lights.get_mut(i) {
+            light.intensity = v.max(0.0);
+        },
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn track(interpolation: Interpolation, keys: &[(f32, f32)]) -> Track<f32> {
+        let mut track = Track::new(interpolation);
+        for &(time, value) in keys {
+            track.insert(time, value);
+        }
+        track
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn step_holds_the_previous_key() {
+        let track = track(Interpolation::Step, &[(0.0, 1.0), (2.0, 3.0), (4.0, -1.0)]);
+        assert_eq!(track.sample(-1.0), Some(1.0));
+        assert_eq!(track.sample(1.0), Some(1.0));
+        assert_eq!(track.sample(2.0), Some(3.0));
+        assert_eq!(track.sample(3.9), Some(3.0));
+        assert_eq!(track.sample(5.0), Some(-1.0));
+        assert_eq!(Track::<f32>::new(Interpolation::Step).sample(1.0), None);
+    }
+
+    #[test]
+    fn linear_goes_straight_between_keys() {
+        // inserted out of order, the track sorts them
+        let track = track(Interpolation::Linear, &[(4.0, -1.0), (0.0, 1.0), (2.0, 3.0)]);
+        assert_close(track.sample(0.0).unwrap(), 1.0);
+        assert_close(track.sample(1.0).unwrap(), 2.0);
+        assert_close(track.sample(2.0).unwrap(), 3.0);
+        assert_close(track.sample(3.0).unwrap(), 1.0);
+        assert_close(track.sample(4.5).unwrap(), -1.0);
+    }
+
+    #[test]
+    fn cubic_passes_through_the_keys() {
+        let track = track(Interpolation::Cubic, &[(0.0, 0.0), (1.0, 1.0), (2.0, 0.0), (3.0, 1.0)]);
+        for (time, value) in [(0.0, 0.0), (1.0, 1.0), (2.0, 0.0), (3.0, 1.0)] {
+            assert_close(track.sample(time).unwrap(), value);
+        }
+        assert_close(track.sample(1.5).unwrap(), 0.5);
+        assert_close(track.sample(1.25).unwrap(), 0.84375);
+        // a straight line stays straight
+        let line = self::track(Interpolation::Cubic, &[(0.0, 0.0), (1.0, 2.0), (2.0, 4.0), (3.0, 6.0)]);
+        assert_close(line.sample(1.5).unwrap(), 3.0);
+    }
+
+    #[test]
+    fn linear_rotations_slerp() {
+        let axis = Vec3D::new(0.0, 1.0, 0.0);
+        let mut track = Track::new(Interpolation::Linear);
+        track.insert(0.0, Quaternion::identity());
+        track.insert(2.0, Quaternion::from_axis_angle(&axis, PI / 2.0));
+        for (time, angle) in [(0.0, 0.0), (1.0, PI / 4.0), (2.0, PI / 2.0)] {
+            let q = track.sample(time).unwrap();
+            assert_close(q.dot(&Quaternion::from_axis_angle(&axis, angle)).abs(), 1.0);
+        }
+    }
+
+    fn animation(looping: bool) -> Animation {
+        let mut animation = Animation::new();
+        animation.looping = looping;
+        animation.add(Target::CameraFov, Channel::Scalar(track(Interpolation::Linear, &[(0.0, 30.0), (4.0, 90.0)])));
+        animation
+    }
+
+    #[test]
+    fn seek_wraps_when_looping() {
+        let mut animation = animation(true);
+        assert_eq!(animation.duration(), 4.0);
+        animation.seek(5.0);
+        assert_close(animation.time(), 1.0);
+        animation.seek(-1.0);
+        assert_close(animation.time(), 3.0);
+
+        let mut animation = self::animation(false);
+        animation.seek(5.0);
+        assert_close(animation.time(), 4.0);
+        animation.seek(-1.0);
+        assert_close(animation.time(), 0.0);
+    }
+
+    #[test]
+    fn advance_loops_or_stops_at_the_end() {
+        let mut animation = animation(true);
+        animation.speed = 2.0;
+        animation.advance(1.5);
+        animation.advance(1.5);
+        assert_close(animation.time(), 2.0);
+        assert!(animation.playing);
+
+        let mut animation = self::animation(false);
+        animation.advance(3.0);
+        animation.advance(3.0);
+        assert_close(animation.time(), 4.0);
+        assert!(!animation.playing);
+        // paused, the time stands still
+        animation.advance(1.0);
+        assert_close(animation.time(), 4.0);
+    }
+}
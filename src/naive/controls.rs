@@ -0,0 +1,155 @@
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use super::gfx::{Camera, Vec3D};
+use super::gfx::camera::MAX_PITCH;
+use super::gfx::vector::{self, cross_product};
+
+/// Inspection controls: the camera circles around a target point.
+/// Dragging with the left mouse button rotates around the target, the wheel zooms and
+/// dragging with the middle button (or shift + left button) pans the target.
+pub struct OrbitController {
+    pub target: Vec3D,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Radians per pixel of mouse movement
+    pub rotate_speed: f32,
+    /// Fraction of the distance per wheel step
+    pub zoom_speed: f32,
+    /// Fraction of the distance per pixel of mouse movement
+    pub pan_speed: f32,
+    pan_modifier: bool
+}
+
+impl OrbitController {
+    pub fn new(target: Vec3D, distance: f32) -> Self {
+        OrbitController {
+            target,
+            distance,
+            yaw: 0.0,
+            pitch: 0.0,
+            rotate_speed: 0.01,
+            zoom_speed: 0.1,
+            pan_speed: 0.002,
+            pan_modifier: false
+        }
+    }
+
+    /// Starts orbiting the point `distance` in front of the camera, keeping the current view
+    pub fn from_camera(camera: &Camera, distance: f32) -> Self {
+        let target = camera.position + vector::vec_mul_by(&camera.forward(), distance);
+        OrbitController { yaw: camera.yaw, pitch: camera.pitch, ..OrbitController::new(target, distance) }
+    }
+
+    /// Handles the mouse events, returns true when the event was consumed
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        match *event {
+            Event::KeyDown { keycode: Some(Keycode::LShift), .. } |
+            Event::KeyDown { keycode: Some(Keycode::RShift), .. } => self.pan_modifier = true,
+            Event::KeyUp { keycode: Some(Keycode::LShift), .. } |
+            Event::KeyUp { keycode: Some(Keycode::RShift), .. } => self.pan_modifier = false,
+            Event::MouseMotion { mousestate, xrel, yrel, .. } => {
+                if mousestate.middle() || (mousestate.left() && self.pan_modifier) {
+                    self.pan(xrel as f32, yrel as f32);
+                } else if mousestate.left() {
+                    self.rotate(xrel as f32, yrel as f32);
+                } else {
+                    return false
+                }
+                return true
+            },
+            Event::MouseWheel { y, .. } => {
+                self.zoom(y as f32);
+                return true
+            },
+            _ => (),
+        }
+        false
+    }
+
+    /// Rotates around the target by a mouse movement in pixels
+    pub fn rotate(&mut self, dx: f32, dy: f32) {
+        self.yaw += dx * self.rotate_speed;
+        self.pitch = (self.pitch - dy * self.rotate_speed).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Moves closer (positive steps) or further away (negative steps)
+    pub fn zoom(&mut self, steps: f32) {
+        self.distance = (self.distance * (1.0 - self.zoom_speed).powf(steps)).max(0.01);
+    }
+
+    /// Drags the target along the view plane by a mouse movement in pixels
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        let camera = self.camera_orientation();
+        let (right, forward) = (camera.right(), camera.forward());
+        let up = cross_product(&right, &forward);
+        let scale = self.distance * self.pan_speed;
+        self.target -= vector::vec_mul_by(&right, dx * scale);
+        self.target += vector::vec_mul_by(&up, dy * scale);
+    }
+
+    /// Targets `center` and backs off until a sphere of `radius` fits into the field of view
+    pub fn focus_sphere(&mut self, center: &Vec3D, radius: f32, camera: &Camera) {
+        let half_fov = (camera.fov * 0.5).to_radians();
+        self.target = *center;
+        self.distance = (radius / half_fov.sin()).max(camera.near * 2.0);
+    }
+
+    /// Places the camera on the orbit, looking at the target
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.yaw = self.yaw;
+        camera.pitch = self.pitch;
+        camera.roll = 0.0;
+        // the orthographic projection zooms with the distance too
+        camera.ortho_size = self.distance * (camera.fov * 0.5).to_radians().tan();
+        camera.position = self.target - vector::vec_mul_by(&camera.forward(), self.distance);
+    }
+
+    /// A camera with the orbit orientation, handy for its direction vectors
+    fn camera_orientation(&self) -> Camera {
+        let mut camera = Camera::new(Vec3D::init(), 90.0, 1.0, 0.1, 1.0);
+        camera.yaw = self.yaw;
+        camera.pitch = self.pitch;
+        camera
+    }
+}
+
+/// Free-fly looking around with the mouse, meant for the SDL relative mouse mode
+/// (`Window::set_relative_mouse_mode`): every mouse movement turns the camera.
+/// The pitch is clamped by `Camera::pitch`, Q and E roll the camera when `roll` is enabled.
+pub struct MouseLook {
+    /// Radians per pixel of mouse movement
+    pub sensitivity: f32,
+    /// Moving the mouse up looks down
+    pub invert_y: bool,
+    pub roll: bool,
+    /// Radians per roll key press
+    pub roll_speed: f32
+}
+
+impl MouseLook {
+    pub fn new() -> Self {
+        MouseLook { sensitivity: 0.003, invert_y: false, roll: false, roll_speed: 0.05 }
+    }
+
+    /// Turns the camera by a relative mouse motion, returns true when the event was consumed
+    pub fn handle_event(&mut self, event: &Event, camera: &mut Camera) -> bool {
+        match *event {
+            Event::MouseMotion { xrel, yrel, .. } => {
+                let dy = if self.invert_y { yrel } else { -yrel };
+                camera.yaw(xrel as f32 * self.sensitivity);
+                camera.pitch(dy as f32 * self.sensitivity);
+                true
+            },
+            Event::KeyDown { keycode: Some(Keycode::Q), .. } if self.roll => {
+                camera.roll(-self.roll_speed);
+                true
+            },
+            Event::KeyDown { keycode: Some(Keycode::E), .. } if self.roll => {
+                camera.roll(self.roll_speed);
+                true
+            },
+            _ => false,
+        }
+    }
+}
@@ -2,8 +2,26 @@ pub mod triangle;
 pub mod matrix;
 pub mod vector;
 pub mod mesh;
+pub mod varying;
+pub mod shading;
+pub mod clip;
+pub mod batch;
+pub mod culling;
+pub mod shader;
+pub mod fog;
+pub mod light;
+pub mod camera;
+pub mod quaternion;
+pub mod bounds;
+pub mod frustum;
+pub mod bvh;
 
 pub use triangle::Triangle;
 pub use vector::Vec3D;
 pub use mesh::Mesh;
-pub use matrix::Matrix;
\ No newline at end of file
+pub use matrix::Matrix;
+pub use varying::Varyings;
+pub use shading::ShadingMode;
+pub use light::Light;
+pub use camera::Camera;
+pub use quaternion::Quaternion;
@@ -0,0 +1,150 @@
+use super::matrix::Matrix;
+use super::vector::Vec3D;
+
+/// Structure-of-arrays storage of vertices, so many of them can be transformed at once with SIMD
+#[derive(Clone, Debug)]
+pub struct VertexBatch {
+    pub x: Vec<f32>,
+    pub y: Vec<f32>,
+    pub z: Vec<f32>,
+    pub w: Vec<f32>
+}
+
+impl VertexBatch {
+    pub fn with_capacity(capacity: usize) -> Self {
+        VertexBatch {
+            x: Vec::with_capacity(capacity),
+            y: Vec::with_capacity(capacity),
+            z: Vec::with_capacity(capacity),
+            w: Vec::with_capacity(capacity)
+        }
+    }
+
+    pub fn from_points(points: &[Vec3D]) -> Self {
+        let mut batch = VertexBatch::with_capacity(points.len());
+        for p in points {
+            batch.push(p);
+        }
+        batch
+    }
+
+    pub fn push(&mut self, p: &Vec3D) {
+        self.x.push(p.x);
+        self.y.push(p.y);
+        self.z.push(p.z);
+        self.w.push(p.w);
+    }
+
+    pub fn len(&self) -> usize {
+        self.x.len()
+    }
+
+    pub fn get(&self, i: usize) -> Vec3D {
+        Vec3D { x: self.x[i], y: self.y[i], z: self.z[i], w: self.w[i] }
+    }
+
+    /// Multiplies every vertex by the matrix, storing the results in `out`
+    pub fn transform_into(&self, m: &Matrix, out: &mut VertexBatch) {
+        let n = self.len();
+        for v in [&mut out.x, &mut out.y, &mut out.z, &mut out.w] {
+            v.resize(n, 0.0);
+        }
+        // SAFETY: SSE is a part of the x86_64 baseline, and every array of `self` and `out`
+        // now holds `n` values
+        #[cfg(target_arch = "x86_64")]
+        let done = unsafe { transform_sse(m, self, out) };
+        #[cfg(not(target_arch = "x86_64"))]
+        let done = 0;
+        transform_scalar(m, self, out, done);
+    }
+
+    /// Scales every vertex to unit length (x, y, z), for batches of normals
+    pub fn normalize(&mut self) {
+        for ((x, y), z) in self.x.iter_mut().zip(self.y.iter_mut()).zip(self.z.iter_mut()) {
+            let len = (*x * *x + *y * *y + *z * *z).sqrt();
+            if len > 0.0 {
+                *x /= len;
+                *y /= len;
+                *z /= len;
+            }
+        }
+    }
+
+    pub fn transform(&self, m: &Matrix) -> VertexBatch {
+        let mut out = VertexBatch::with_capacity(self.len());
+        self.transform_into(m, &mut out);
+        out
+    }
+}
+
+/// Portable path, transforms the vertices starting at `from`.
+/// Keeps the same order of operations as `Matrix::apply`.
+fn transform_scalar(m: &Matrix, src: &VertexBatch, dst: &mut VertexBatch, from: usize) {
+    let v = &m.values;
+    for i in from..src.len() {
+        let (x, y, z, w) = (src.x[i], src.y[i], src.z[i], src.w[i]);
+        dst.x[i] = x * v[0][0] + y * v[1][0] + z * v[2][0] + w * v[3][0];
+        dst.y[i] = x * v[0][1] + y * v[1][1] + z * v[2][1] + w * v[3][1];
+        dst.z[i] = x * v[0][2] + y * v[1][2] + z * v[2][2] + w * v[3][2];
+        dst.w[i] = x * v[0][3] + y * v[1][3] + z * v[2][3] + w * v[3][3];
+    }
+}
+
+/// Transforms four vertices per iteration and returns how many were processed,
+/// the rest is left for the scalar path.
+///
+/// # Safety
+/// `x`, `y`, `z` and `w` of both batches must all have the length of `src`: the loads and stores
+/// go through raw pointers without bounds checks.
+#[cfg(target_arch = "x86_64")]
+unsafe fn transform_sse(m: &Matrix, src: &VertexBatch, dst: &mut VertexBatch) -> usize {
+    use std::arch::x86_64::*;
+
+    let n = src.len();
+    debug_assert!([src.y.len(), src.z.len(), src.w.len(), dst.x.len(), dst.y.len(), dst.z.len(), dst.w.len()]
+        .iter().all(|&len| len == n));
+
+    let v = &m.values;
+    let col = |c: usize| [_mm_set1_ps(v[0][c]), _mm_set1_ps(v[1][c]), _mm_set1_ps(v[2][c]), _mm_set1_ps(v[3][c])];
+    let cols = [col(0), col(1), col(2), col(3)];
+    let count = n / 4 * 4;
+
+    for i in (0..count).step_by(4) {
+        let x = _mm_loadu_ps(src.x.as_ptr().add(i));
+        let y = _mm_loadu_ps(src.y.as_ptr().add(i));
+        let z = _mm_loadu_ps(src.z.as_ptr().add(i));
+        let w = _mm_loadu_ps(src.w.as_ptr().add(i));
+        let out = [dst.x.as_mut_ptr(), dst.y.as_mut_ptr(), dst.z.as_mut_ptr(), dst.w.as_mut_ptr()];
+        for c in 0..4 {
+            let r = _mm_add_ps(_mm_add_ps(_mm_add_ps(_mm_mul_ps(x, cols[c][0]), _mm_mul_ps(y, cols[c][1])),
+                                          _mm_mul_ps(z, cols[c][2])),
+                               _mm_mul_ps(w, cols[c][3]));
+            _mm_storeu_ps(out[c].add(i), r);
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch_matches_matrix_apply() {
+        let mut m = Matrix::init();
+        for (i, value) in m.values.iter_mut().flatten().enumerate() {
+            *value = i as f32 * 0.37 - 2.5;
+        }
+        // 7 vertices: a full SSE group of four and three left for the scalar path
+        let points: Vec<Vec3D> = (0..7)
+            .map(|i| Vec3D { x: i as f32 * 1.5 - 4.0, y: 3.0 - i as f32, z: i as f32 * 0.25, w: 1.0 - i as f32 * 0.1 })
+            .collect();
+        let out = VertexBatch::from_points(&points).transform(&m);
+        assert_eq!(out.len(), points.len());
+        for (i, p) in points.iter().enumerate() {
+            let expected = m.apply(p);
+            let got = out.get(i);
+            assert_eq!((got.x, got.y, got.z, got.w), (expected.x, expected.y, expected.z, expected.w), "vertex {}", i);
+        }
+    }
+}
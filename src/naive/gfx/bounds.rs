@@ -0,0 +1,109 @@
+use super::matrix::Matrix;
+use super::vector::{self, Vec3D};
+
+/// Axis aligned bounding box
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb {
+    pub min: Vec3D,
+    pub max: Vec3D
+}
+
+impl Aabb {
+    /// A box containing nothing, growing it by a point gives a box around that point
+    pub fn empty() -> Self {
+        Aabb {
+            min: Vec3D::new(f32::MAX, f32::MAX, f32::MAX),
+            max: Vec3D::new(f32::MIN, f32::MIN, f32::MIN)
+        }
+    }
+
+    pub fn from_points(points: &[Vec3D]) -> Self {
+        let mut aabb = Aabb::empty();
+        for p in points {
+            aabb.grow(p);
+        }
+        aabb
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn grow(&mut self, p: &Vec3D) {
+        self.min = Vec3D::new(self.min.x.min(p.x), self.min.y.min(p.y), self.min.z.min(p.z));
+        self.max = Vec3D::new(self.max.x.max(p.x), self.max.y.max(p.y), self.max.z.max(p.z));
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        let mut aabb = *self;
+        aabb.grow(&other.min);
+        aabb.grow(&other.max);
+        aabb
+    }
+
+    pub fn center(&self) -> Vec3D {
+        vector::vec_mul_by(&(self.min + self.max), 0.5)
+    }
+
+    pub fn size(&self) -> Vec3D {
+        self.max - self.min
+    }
+
+    /// Area of the six faces, the cost measure of the BVH build
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0
+        }
+        let s = self.size();
+        2.0 * (s.x * s.y + s.y * s.z + s.z * s.x)
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x && self.max.x >= other.min.x &&
+            self.min.y <= other.max.y && self.max.y >= other.min.y &&
+            self.min.z <= other.max.z && self.max.z >= other.min.z
+    }
+
+    /// Distance from a point to the box, zero inside
+    pub fn distance(&self, p: &Vec3D) -> f32 {
+        let dx = (self.min.x - p.x).max(0.0).max(p.x - self.max.x);
+        let dy = (self.min.y - p.y).max(0.0).max(p.y - self.max.y);
+        let dz = (self.min.z - p.z).max(0.0).max(p.z - self.max.z);
+        (dx * dx + dy * dy + dz * dz).sqrt()
+    }
+
+    /// Returns the corner furthest along `direction`
+    pub fn support(&self, direction: &Vec3D) -> Vec3D {
+        Vec3D::new(
+            if direction.x >= 0.0 { self.max.x } else { self.min.x },
+            if direction.y >= 0.0 { self.max.y } else { self.min.y },
+            if direction.z >= 0.0 { self.max.z } else { self.min.z }
+        )
+    }
+
+    /// Sphere (center, radius) around the box
+    pub fn bounding_sphere(&self) -> (Vec3D, f32) {
+        if self.is_empty() {
+            return (Vec3D::init(), 0.0)
+        }
+        (self.center(), vector::vec_mul_by(&self.size(), 0.5).len())
+    }
+
+    /// Box around this one placed by `transform`: encloses the eight transformed corners
+    pub fn transformed(&self, transform: &Matrix) -> Aabb {
+        if self.is_empty() {
+            return *self
+        }
+        let mut aabb = Aabb::empty();
+        for i in 0..8 {
+            let corner = Vec3D::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z }
+            );
+            aabb.grow(&transform.apply(&corner));
+        }
+        aabb
+    }
+}
@@ -0,0 +1,464 @@
+use super::bounds::Aabb;
+use super::matrix::Matrix;
+use super::mesh::Mesh;
+use super::vector::{self, cross_product, Vec3D};
+
+/// Number of buckets the centroids are sorted into when looking for the best split
+const SAH_BINS: usize = 12;
+/// Cost of visiting a node relative to testing one primitive
+const TRAVERSAL_COST: f32 = 1.0;
+/// Leaves this small are never split further
+const MIN_LEAF: usize = 2;
+
+/// A half line from `origin` along `direction`, points on it are `origin + t * direction` for t >= 0
+#[derive(Copy, Clone, Debug)]
+pub struct Ray {
+    pub origin: Vec3D,
+    pub direction: Vec3D
+}
+
+impl Ray {
+    /// A ray with a normalized direction, so t is the distance from the origin
+    pub fn new(origin: Vec3D, direction: Vec3D) -> Self {
+        let mut direction = direction;
+        direction.normalize();
+        Ray { origin, direction }
+    }
+
+    pub fn at(&self, t: f32) -> Vec3D {
+        self.origin + vector::vec_mul_by(&self.direction, t)
+    }
+
+    /// The same ray in the space of `transform`. The direction is not normalized again,
+    /// so hits keep their t.
+    pub fn transformed(&self, transform: &Matrix) -> Ray {
+        let direction = Vec3D { w: 0.0, ..self.direction };
+        let mut direction = transform.apply(&direction);
+        direction.w = 1.0;
+        Ray { origin: transform.apply(&self.origin), direction }
+    }
+
+    /// Returns the t where the ray enters the box (0 when it starts inside), `None` on a miss
+    /// or when the box is further than `max_t`
+    pub fn hit_aabb(&self, aabb: &Aabb, max_t: f32) -> Option<f32> {
+        let (mut near, mut far) = (0.0f32, max_t);
+        for a in 0..3 {
+            let inv = 1.0 / axis(&self.direction, a);
+            let t0 = (axis(&aabb.min, a) - axis(&self.origin, a)) * inv;
+            let t1 = (axis(&aabb.max, a) - axis(&self.origin, a)) * inv;
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+        if near <= far { Some(near) } else { None }
+    }
+
+    /// Möller–Trumbore intersection, both sides of the triangle are hit.
+    /// Returns t and the barycentric weights (u, v) of the second and the third vertex.
+    pub fn hit_triangle(&self, p: &[Vec3D; 3]) -> Option<(f32, f32, f32)> {
+        let (edge1, edge2) = (p[1] - p[0], p[2] - p[0]);
+        let h = cross_product(&self.direction, &edge2);
+        let det = edge1.dot_product(&h);
+        if det.abs() < 1e-9 {
+            return None
+        }
+        let inv_det = 1.0 / det;
+        let s = self.origin - p[0];
+        let u = s.dot_product(&h) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None
+        }
+        let q = cross_product(&s, &edge1);
+        let v = self.direction.dot_product(&q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None
+        }
+        let t = edge2.dot_product(&q) * inv_det;
+        if t >= 0.0 { Some((t, u, v)) } else { None }
+    }
+}
+
+fn axis(v: &Vec3D, a: usize) -> f32 {
+    match a {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+/// Inner nodes have `count == 0` and their children at `left` and `left + 1`,
+/// leaves hold `count` primitives from `start` in `Bvh::indices`
+#[derive(Copy, Clone, Debug)]
+struct BvhNode {
+    bounds: Aabb,
+    left: usize,
+    start: usize,
+    count: usize
+}
+
+/// Bounding volume hierarchy over any primitives given by their boxes.
+/// Built top down: every node is split where the surface area heuristic (SAH) expects the
+/// cheapest traversal, with the primitive centroids binned along each axis.
+/// The queries only walk the boxes, the primitives themselves are tested by the caller's closures.
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<usize>
+}
+
+impl Bvh {
+    pub fn build(bounds: &[Aabb]) -> Self {
+        let mut bvh = Bvh { nodes: Vec::new(), indices: (0..bounds.len()).collect() };
+        if bounds.is_empty() {
+            return bvh
+        }
+        let centroids: Vec<Vec3D> = bounds.iter().map(|b| b.center()).collect();
+        bvh.nodes.push(BvhNode { bounds: Aabb::empty(), left: 0, start: 0, count: bounds.len() });
+        let mut pending = vec![0];
+        while let Some(node) = pending.pop() {
+            if let Some(children) = bvh.subdivide(node, bounds, &centroids) {
+                pending.push(children);
+                pending.push(children + 1);
+            }
+        }
+        bvh
+    }
+
+    /// Box around everything in the hierarchy
+    pub fn bounds(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::empty(), |n| n.bounds)
+    }
+
+    /// Fits the node around its primitives and splits it in two when it pays off,
+    /// returns the index of the first child
+    fn subdivide(&mut self, id: usize, bounds: &[Aabb], centroids: &[Vec3D]) -> Option<usize> {
+        let BvhNode { start, count, .. } = self.nodes[id];
+        let primitives = &mut self.indices[start..start + count];
+        let mut node_bounds = Aabb::empty();
+        let mut centroid_bounds = Aabb::empty();
+        for &i in primitives.iter() {
+            node_bounds = node_bounds.union(&bounds[i]);
+            centroid_bounds.grow(&centroids[i]);
+        }
+        self.nodes[id].bounds = node_bounds;
+        if count <= MIN_LEAF {
+            return None
+        }
+
+        let bin_of = |a: usize, c: &Vec3D| {
+            let (min, max) = (axis(&centroid_bounds.min, a), axis(&centroid_bounds.max, a));
+            (((axis(c, a) - min) / (max - min) * SAH_BINS as f32) as usize).min(SAH_BINS - 1)
+        };
+        // (cost, axis, first bin of the right side)
+        let mut best: Option<(f32, usize, usize)> = None;
+        for a in 0..3 {
+            if axis(&centroid_bounds.max, a) - axis(&centroid_bounds.min, a) <= 0.0 {
+                continue
+            }
+            let mut bins = [(Aabb::empty(), 0usize); SAH_BINS];
+            for &i in primitives.iter() {
+                let bin = &mut bins[bin_of(a, &centroids[i])];
+                bin.0 = bin.0.union(&bounds[i]);
+                bin.1 += 1;
+            }
+            // sweep from the right, then evaluate every split from the left
+            let mut right = [(0.0f32, 0usize); SAH_BINS];
+            let (mut area, mut n) = (Aabb::empty(), 0);
+            for b in (1..SAH_BINS).rev() {
+                area = area.union(&bins[b].0);
+                n += bins[b].1;
+                right[b] = (area.surface_area(), n);
+            }
+            let (mut area, mut n) = (Aabb::empty(), 0);
+            for b in 1..SAH_BINS {
+                area = area.union(&bins[b - 1].0);
+                n += bins[b - 1].1;
+                if n == 0 || right[b].1 == 0 {
+                    continue
+                }
+                let cost = area.surface_area() * n as f32 + right[b].0 * right[b].1 as f32;
+                if best.is_none_or(|(c, _, _)| cost < c) {
+                    best = Some((cost, a, b));
+                }
+            }
+        }
+
+        let (cost, a, split) = best?;
+        let cost = TRAVERSAL_COST + cost / node_bounds.surface_area().max(1e-12);
+        if cost >= count as f32 {
+            return None
+        }
+
+        let mut middle = 0;
+        for j in 0..count {
+            if bin_of(a, &centroids[primitives[j]]) < split {
+                primitives.swap(j, middle);
+                middle += 1;
+            }
+        }
+        let left = self.nodes.len();
+        self.nodes.push(BvhNode { bounds: Aabb::empty(), left: 0, start, count: middle });
+        self.nodes.push(BvhNode { bounds: Aabb::empty(), left: 0, start: start + middle, count: count - middle });
+        self.nodes[id].left = left;
+        self.nodes[id].count = 0;
+        Some(left)
+    }
+
+    /// Best-first search for the closest primitive. `lower_bound` tells how close anything in a box
+    /// can be (`None` skips the box), `test` measures a primitive and returns a result when it's
+    /// closer than the given limit. Nodes further than the best result so far are not visited.
+    pub fn closest<T, B, F>(&self, max: f32, lower_bound: B, mut test: F) -> Option<(f32, T)>
+        where B: Fn(&Aabb) -> Option<f32>, F: FnMut(usize, f32) -> Option<(f32, T)> {
+        let mut best: Option<(f32, T)> = None;
+        let mut limit = max;
+        let root = match self.nodes.first() {
+            Some(root) => lower_bound(&root.bounds),
+            None => return None
+        };
+        let mut stack = match root {
+            Some(d) => vec![(0, d)],
+            None => return None
+        };
+        while let Some((id, distance)) = stack.pop() {
+            if distance > limit {
+                continue
+            }
+            let node = &self.nodes[id];
+            if node.count > 0 {
+                for &i in &self.indices[node.start..node.start + node.count] {
+                    if let Some((d, value)) = test(i, limit) {
+                        if d <= limit {
+                            limit = d;
+                            best = Some((d, value));
+                        }
+                    }
+                }
+                continue
+            }
+            let near = lower_bound(&self.nodes[node.left].bounds).map(|d| (node.left, d));
+            let far = lower_bound(&self.nodes[node.left + 1].bounds).map(|d| (node.left + 1, d));
+            // the nearer child goes on top of the stack
+            match (near, far) {
+                (Some(a), Some(b)) if a.1 <= b.1 => { stack.push(b); stack.push(a) },
+                (Some(a), Some(b)) => { stack.push(a); stack.push(b) },
+                (Some(a), None) | (None, Some(a)) => stack.push(a),
+                (None, None) => (),
+            }
+        }
+        best
+    }
+
+    /// Closest primitive along the ray, see `closest`
+    pub fn ray_cast<T, F>(&self, ray: &Ray, max_t: f32, test: F) -> Option<(f32, T)>
+        where F: FnMut(usize, f32) -> Option<(f32, T)> {
+        self.closest(max_t, |b| ray.hit_aabb(b, max_t), test)
+    }
+
+    /// Primitives of the leaves overlapping the box: candidates, which the caller still has to test
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn overlapping(&self, aabb: &Aabb) -> Vec<usize> {
+        let mut result = Vec::new();
+        let mut stack = if self.nodes.is_empty() { vec![] } else { vec![0] };
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id];
+            if !node.bounds.overlaps(aabb) {
+                continue
+            }
+            if node.count > 0 {
+                result.extend_from_slice(&self.indices[node.start..node.start + node.count]);
+            } else {
+                stack.push(node.left);
+                stack.push(node.left + 1);
+            }
+        }
+        result
+    }
+}
+
+/// A ray hitting a triangle: distance along the ray, the triangle index, the hit point and the
+/// barycentric weights of the second and the third vertex
+#[derive(Copy, Clone, Debug)]
+pub struct RayHit {
+    pub t: f32,
+    pub triangle: usize,
+    pub point: Vec3D,
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub u: f32,
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub v: f32
+}
+
+/// The closest point of a surface to a query point
+#[derive(Copy, Clone, Debug)]
+pub struct NearestPoint {
+    pub triangle: usize,
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub point: Vec3D,
+    pub distance: f32
+}
+
+/// BVH over the triangles of a mesh, in model space. Indices of the results are indices of
+/// `Mesh::tris` and `Mesh::faces`.
+pub struct MeshBvh {
+    pub bvh: Bvh,
+    triangles: Vec<[Vec3D; 3]>
+}
+
+impl MeshBvh {
+    pub fn new(mesh: &Mesh) -> Self {
+        let triangles: Vec<[Vec3D; 3]> = mesh.faces.iter().map(|f| f.map(|v| mesh.positions.get(v))).collect();
+        let bounds: Vec<Aabb> = triangles.iter().map(|t| Aabb::from_points(t)).collect();
+        MeshBvh { bvh: Bvh::build(&bounds), triangles }
+    }
+
+    pub fn triangle(&self, index: usize) -> &[Vec3D; 3] {
+        &self.triangles[index]
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.bvh.bounds()
+    }
+
+    /// First triangle along the ray, up to `max_t`
+    pub fn intersect_ray(&self, ray: &Ray, max_t: f32) -> Option<RayHit> {
+        self.bvh.ray_cast(ray, max_t, |i, _| {
+            ray.hit_triangle(&self.triangles[i]).map(|(t, u, v)| (t, (i, u, v)))
+        }).map(|(t, (triangle, u, v))| RayHit { t, triangle, point: ray.at(t), u, v })
+    }
+
+    /// Triangles touching the box. The test is exact only up to the triangle boxes,
+    /// a triangle passing diagonally next to the box can be reported as well.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn overlapping(&self, aabb: &Aabb) -> Vec<usize> {
+        let mut result = self.bvh.overlapping(aabb);
+        result.retain(|&i| Aabb::from_points(&self.triangles[i]).overlaps(aabb));
+        result
+    }
+
+    /// Closest point of the mesh to `point`, looking no further than `max_distance`
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn nearest_point(&self, point: &Vec3D, max_distance: f32) -> Option<NearestPoint> {
+        self.bvh.closest(max_distance, |b| Some(b.distance(point)), |i, _| {
+            let closest = closest_point_on_triangle(point, &self.triangles[i]);
+            Some(((closest - *point).len(), (i, closest)))
+        }).map(|(distance, (triangle, point))| NearestPoint { triangle, point, distance })
+    }
+
+    /// Same as `nearest_point`, with the mesh placed in the world by `transform`.
+    /// Distances are measured in the world, so any scale is fine.
+    pub fn nearest_point_transformed(&self, point: &Vec3D, transform: &Matrix, max_distance: f32) -> Option<NearestPoint> {
+        self.bvh.closest(max_distance, |b| Some(b.transformed(transform).distance(point)), |i, _| {
+            let triangle = self.triangles[i].map(|p| transform.apply(&p));
+            let closest = closest_point_on_triangle(point, &triangle);
+            Some(((closest - *point).len(), (i, closest)))
+        }).map(|(distance, (triangle, point))| NearestPoint { triangle, point, distance })
+    }
+}
+
+/// Closest point of a triangle to `p`, by the Voronoi region of the triangle `p` falls into
+/// (Ericson, Real-Time Collision Detection 5.1.5)
+pub fn closest_point_on_triangle(p: &Vec3D, tri: &[Vec3D; 3]) -> Vec3D {
+    let [a, b, c] = *tri;
+    let (ab, ac, ap) = (b - a, c - a, *p - a);
+    let (d1, d2) = (ab.dot_product(&ap), ac.dot_product(&ap));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a
+    }
+    let bp = *p - b;
+    let (d3, d4) = (ab.dot_product(&bp), ac.dot_product(&bp));
+    if d3 >= 0.0 && d4 <= d3 {
+        return b
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + vector::vec_mul_by(&ab, d1 / (d1 - d3))
+    }
+    let cp = *p - c;
+    let (d5, d6) = (ab.dot_product(&cp), ac.dot_product(&cp));
+    if d6 >= 0.0 && d5 <= d6 {
+        return c
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + vector::vec_mul_by(&ac, d2 / (d2 - d6))
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return b + vector::vec_mul_by(&(c - b), (d4 - d3) / ((d4 - d3) + (d5 - d6)))
+    }
+    let denom = 1.0 / (va + vb + vc);
+    a + vector::vec_mul_by(&ab, vb * denom) + vector::vec_mul_by(&ac, vc * denom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pseudo-random points in a box twice the size of `bounds`
+    fn points_around(bounds: &Aabb, count: usize) -> Vec<Vec3D> {
+        let mut seed = 42u32;
+        let mut next = || {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (seed >> 8) as f32 / (1 << 24) as f32 * 2.0 - 0.5
+        };
+        let size = bounds.size();
+        (0..count).map(|_| {
+            Vec3D::new(bounds.min.x + next() * size.x, bounds.min.y + next() * size.y, bounds.min.z + next() * size.z)
+        }).collect()
+    }
+
+    fn teapot() -> (Mesh, MeshBvh) {
+        let mesh = Mesh::load_object("example_objs/teapot.obj").unwrap();
+        let bvh = MeshBvh::new(&mesh);
+        (mesh, bvh)
+    }
+
+    #[test]
+    fn ray_hits_match_brute_force() {
+        let (mesh, bvh) = teapot();
+        let points = points_around(&bvh.bounds(), 200);
+        for pair in points.chunks(2) {
+            let ray = Ray::new(pair[0], pair[1] - pair[0]);
+            let expected = (0..mesh.faces.len())
+                .filter_map(|i| ray.hit_triangle(bvh.triangle(i)).map(|(t, _, _)| t))
+                .fold(None, |best: Option<f32>, t| Some(best.map_or(t, |b| b.min(t))));
+            let hit = bvh.intersect_ray(&ray, f32::MAX);
+            assert_eq!(hit.map(|h| h.t), expected);
+            if let Some(hit) = hit {
+                // the barycentric weights lead back to the hit point
+                let [a, b, c] = *bvh.triangle(hit.triangle);
+                let p = a + vector::vec_mul_by(&(b - a), hit.u) + vector::vec_mul_by(&(c - a), hit.v);
+                assert!((p - hit.point).len() < 1e-3, "{:?} is not on triangle {}", hit.point, hit.triangle);
+            }
+        }
+    }
+
+    #[test]
+    fn overlapping_matches_brute_force() {
+        let (mesh, bvh) = teapot();
+        let points = points_around(&bvh.bounds(), 100);
+        for pair in points.chunks(2) {
+            let aabb = Aabb::from_points(pair);
+            let mut found = bvh.overlapping(&aabb);
+            found.sort_unstable();
+            let expected: Vec<usize> = (0..mesh.faces.len())
+                .filter(|&i| Aabb::from_points(bvh.triangle(i)).overlaps(&aabb))
+                .collect();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn nearest_point_matches_brute_force() {
+        let (mesh, bvh) = teapot();
+        for p in points_around(&bvh.bounds(), 100) {
+            let expected = (0..mesh.faces.len())
+                .map(|i| (closest_point_on_triangle(&p, bvh.triangle(i)) - p).len())
+                .fold(f32::MAX, f32::min);
+            let nearest = bvh.nearest_point(&p, f32::MAX).unwrap();
+            assert!((nearest.distance - expected).abs() <= 1e-4 * expected.max(1.0));
+            let on_triangle = closest_point_on_triangle(&p, bvh.triangle(nearest.triangle));
+            assert!((on_triangle - nearest.point).len() < 1e-4);
+            // nothing within a limit closer than the nearest point
+            assert!(bvh.nearest_point(&p, 0.9 * expected).is_none());
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use super::bvh::Ray;
+use super::matrix::Matrix;
+use super::vector::{self, Vec3D};
+
+/// Pitch stays just under straight up/down, so the view never flips over
+pub const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+
+/// How the camera projects the view
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    Perspective,
+    /// Parallel rays, no foreshortening: sizes don't depend on the distance
+    Orthographic,
+}
+
+/// Axis aligned views for technical inspection
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CanonicalView {
+    /// Looks along +z
+    Front,
+    /// Looks straight down
+    Top,
+    /// Looks along -x
+    Side,
+}
+
+impl CanonicalView {
+    /// Returns the (yaw, pitch) of the view
+    pub fn angles(&self) -> (f32, f32) {
+        use std::f32::consts::FRAC_PI_2;
+        match self {
+            CanonicalView::Front => (0.0, 0.0),
+            CanonicalView::Top => (0.0, -FRAC_PI_2),
+            CanonicalView::Side => (FRAC_PI_2, 0.0),
+        }
+    }
+}
+
+/// A camera: where it is, where it looks and how it projects.
+/// The orientation is kept as yaw (around the world y axis), pitch (up and down) and
+/// roll (around the view direction) in radians, a zero yaw looks along +z.
+#[derive(Copy, Clone, Debug)]
+pub struct Camera {
+    pub position: Vec3D,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub roll: f32,
+    /// Vertical field of view in degrees
+    pub fov: f32,
+    /// Height / width of the target, as `Matrix::init_projection` expects it
+    pub aspect_ratio: f32,
+    pub near: f32,
+    pub far: f32,
+    pub projection: Projection,
+    /// Half of the view height in world units, used by the orthographic projection
+    pub ortho_size: f32
+}
+
+impl Camera {
+    pub fn new(position: Vec3D, fov: f32, aspect_ratio: f32, near: f32, far: f32) -> Self {
+        Camera {
+            position, yaw: 0.0, pitch: 0.0, roll: 0.0, fov, aspect_ratio, near, far,
+            projection: Projection::Perspective,
+            ortho_size: 10.0
+        }
+    }
+
+    /// Normalized direction the camera looks at
+    pub fn forward(&self) -> Vec3D {
+        let (sy, cy) = self.yaw.sin_cos();
+        let (sp, cp) = self.pitch.sin_cos();
+        Vec3D::new(-sy * cp, sp, cy * cp)
+    }
+
+    /// Normalized horizontal direction to the right of the view
+    pub fn right(&self) -> Vec3D {
+        let (sy, cy) = self.yaw.sin_cos();
+        Vec3D::new(-cy, 0.0, -sy)
+    }
+
+    /// Normalized up direction of the view, tilted by the roll
+    pub fn up(&self) -> Vec3D {
+        let (forward, right) = (self.forward(), self.right());
+        let up = vector::cross_product(&right, &forward);
+        let (sr, cr) = self.roll.sin_cos();
+        vector::vec_mul_by(&up, cr) + vector::vec_mul_by(&right, sr)
+    }
+
+    /// World to view space
+    pub fn view_matrix(&self) -> Matrix {
+        // the screen y axis points down, so the up vector is flipped
+        let up = vector::vec_mul_by(&self.up(), -1.0);
+        let target = self.position + self.forward();
+        Matrix::point_at(&self.position, &target, &up).quick_inverse()
+    }
+
+    /// Direction of the sight line from the camera to a world space point,
+    /// with the orthographic projection it's the same everywhere
+    pub fn view_ray(&self, point: &Vec3D) -> Vec3D {
+        match self.projection {
+            Projection::Perspective => *point - self.position,
+            Projection::Orthographic => self.forward(),
+        }
+    }
+
+    /// Switches between the perspective and the orthographic projection. The orthographic view
+    /// gets sized so things at `focus_distance` keep their size on the screen.
+    pub fn toggle_projection(&mut self, focus_distance: f32) {
+        self.projection = match self.projection {
+            Projection::Perspective => {
+                self.ortho_size = focus_distance * (self.fov * 0.5).to_radians().tan();
+                Projection::Orthographic
+            },
+            Projection::Orthographic => Projection::Perspective,
+        };
+    }
+
+    /// Looks at `target` from `distance` away along one of the canonical views
+    pub fn look_from(&mut self, view: CanonicalView, target: &Vec3D, distance: f32) {
+        let (yaw, pitch) = view.angles();
+        self.yaw = yaw;
+        self.pitch = pitch;
+        self.roll = 0.0;
+        self.position = *target - vector::vec_mul_by(&self.forward(), distance);
+    }
+
+    /// View to clip space
+    pub fn projection_matrix(&self) -> Matrix {
+        match self.projection {
+            Projection::Perspective => Matrix::init_projection(self.fov, self.aspect_ratio, self.near, self.far),
+            Projection::Orthographic => Matrix::init_orthographic(self.ortho_size, self.aspect_ratio, self.near, self.far),
+        }
+    }
+
+    /// World to clip space, the view and the projection combined
+    pub fn view_projection(&self) -> Matrix {
+        self.view_matrix() * self.projection_matrix()
+    }
+
+    /// World space ray through a point of the screen (pixels of a `width` x `height` target, y down),
+    /// starting on the near plane. Unprojects the point at the near and the far plane with the
+    /// inverse of the view and projection, so it works with both projections.
+    pub fn screen_ray(&self, x: f32, y: f32, width: f32, height: f32) -> Option<Ray> {
+        let inverse = self.view_projection().inverse()?;
+        let (ndc_x, ndc_y) = (2.0 * x / width - 1.0, 2.0 * y / height - 1.0);
+        let unproject = |depth: f32| {
+            let p = inverse.apply(&Vec3D { x: ndc_x, y: ndc_y, z: depth, w: 1.0 });
+            vector::vec_div_by(&p, p.w)
+        };
+        let (near, far) = (unproject(0.0), unproject(1.0));
+        Some(Ray::new(near, far - near))
+    }
+
+    /// Moves along the view direction, negative distances move backwards
+    pub fn move_forward(&mut self, distance: f32) {
+        self.position += vector::vec_mul_by(&self.forward(), distance);
+    }
+
+    /// Moves sideways, positive distances move to the right
+    pub fn strafe(&mut self, distance: f32) {
+        self.position += vector::vec_mul_by(&self.right(), distance);
+    }
+
+    /// Turns left (negative) or right (positive) by the angle in radians
+    pub fn yaw(&mut self, angle: f32) {
+        self.yaw += angle;
+    }
+
+    /// Looks up (positive) or down (negative) by the angle in radians
+    pub fn pitch(&mut self, angle: f32) {
+        self.pitch = (self.pitch + angle).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Tilts the camera to the right (positive) or to the left (negative) by the angle in radians
+    pub fn roll(&mut self, angle: f32) {
+        self.roll += angle;
+    }
+}
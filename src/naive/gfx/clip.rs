@@ -0,0 +1,84 @@
+use super::triangle::Triangle;
+use super::vector::{Vec3D, vec_lerp};
+use super::varying::Varyings;
+
+/// A polygon vertex during clipping: position in clip space, normal and varyings
+#[derive(Copy, Clone)]
+struct ClipVertex {
+    p: Vec3D,
+    n: Vec3D,
+    var: Varyings
+}
+
+const FRUSTUM_PLANES: usize = 6;
+
+/// Signed distance of a clip space point to one of the frustum planes, positive inside.
+/// Depth is expected in the `0..w` range produced by `Matrix::init_projection`.
+fn plane_distance(plane: usize, p: &Vec3D) -> f32 {
+    match plane {
+        0 => p.w + p.x, // left
+        1 => p.w - p.x, // right
+        2 => p.w + p.y, // top
+        3 => p.w - p.y, // bottom
+        4 => p.z,       // near
+        _ => p.w - p.z, // far
+    }
+}
+
+fn lerp_vertex(a: &ClipVertex, b: &ClipVertex, t: f32) -> ClipVertex {
+    ClipVertex {
+        p: vec_lerp(&a.p, &b.p, t),
+        n: vec_lerp(&a.n, &b.n, t),
+        var: Varyings::lerp(&a.var, &b.var, t)
+    }
+}
+
+/// Clips a triangle in homogeneous clip space (after the projection matrix, before the
+/// perspective divide) against all six frustum planes.
+/// Sutherland–Hodgman produces a convex polygon which is then fanned back into triangles.
+/// Positions, normals and varyings of the new vertices are interpolated along the clipped edges.
+pub fn clip_triangle(tri: &Triangle) -> Vec<Triangle> {
+    let inside = |p: &Vec3D| (0..FRUSTUM_PLANES).all(|plane| plane_distance(plane, p) >= 0.0);
+    if tri.p.iter().all(inside) {
+        return vec![*tri]
+    }
+
+    let mut polygon: Vec<ClipVertex> = (0..3)
+        .map(|i| ClipVertex { p: tri.p[i], n: tri.n[i], var: tri.var[i] })
+        .collect();
+
+    for plane in 0..FRUSTUM_PLANES {
+        if polygon.is_empty() {
+            break
+        }
+        let mut output = Vec::with_capacity(polygon.len() + 1);
+        for i in 0..polygon.len() {
+            let current = &polygon[i];
+            let next = &polygon[(i + 1) % polygon.len()];
+            let d_current = plane_distance(plane, &current.p);
+            let d_next = plane_distance(plane, &next.p);
+
+            if d_current >= 0.0 {
+                output.push(*current);
+            }
+            if (d_current >= 0.0) != (d_next >= 0.0) {
+                output.push(lerp_vertex(current, next, d_current / (d_current - d_next)));
+            }
+        }
+        polygon = output;
+    }
+
+    let mut result = Vec::new();
+    for i in 1..polygon.len().saturating_sub(1) {
+        let (a, b, c) = (&polygon[0], &polygon[i], &polygon[i + 1]);
+        result.push(Triangle {
+            p: [a.p, b.p, c.p],
+            n: [a.n, b.n, c.n],
+            var: [a.var, b.var, c.var],
+            material: tri.material,
+            base_color: tri.base_color,
+            color: tri.color
+        });
+    }
+    result
+}
@@ -0,0 +1,71 @@
+use super::vector::Vec3D;
+
+/// Which faces get discarded before rasterization
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CullMode {
+    /// Draws both sides, back faces are lit with flipped normals
+    None,
+    Back,
+    Front,
+}
+
+/// Vertex order of the front faces as seen by the viewer
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Winding {
+    Clockwise,
+    /// The OBJ convention
+    CounterClockwise,
+}
+
+impl CullMode {
+    /// Cycles no culling → back faces → front faces
+    pub fn next(&self) -> CullMode {
+        match self {
+            CullMode::None => CullMode::Back,
+            CullMode::Back => CullMode::Front,
+            CullMode::Front => CullMode::None,
+        }
+    }
+
+    /// Whether a face facing the given way survives the culling
+    pub fn keeps(&self, front_facing: bool) -> bool {
+        match self {
+            CullMode::None => true,
+            CullMode::Back => front_facing,
+            CullMode::Front => !front_facing,
+        }
+    }
+}
+
+impl Winding {
+    pub fn flipped(&self) -> Winding {
+        match self {
+            Winding::Clockwise => Winding::CounterClockwise,
+            Winding::CounterClockwise => Winding::Clockwise,
+        }
+    }
+}
+
+/// Checks whether a face is turned towards the viewer.
+/// `normal` is the face normal built from the vertex order (p1 - p0) x (p2 - p0),
+/// `view_ray` goes from the viewer to the face.
+pub fn is_front_facing(normal: &Vec3D, view_ray: &Vec3D, winding: Winding) -> bool {
+    let d = normal.dot_product(view_ray);
+    match winding {
+        Winding::CounterClockwise => d < 0.0,
+        Winding::Clockwise => d > 0.0,
+    }
+}
+
+/// Same test for a triangle in clip space, before the perspective divide.
+/// The sign of the determinant of the (x, y, w) rows is the screen space orientation,
+/// and unlike the projected area it stays valid for vertices behind the viewer.
+pub fn is_front_facing_clip(p: &[Vec3D; 3], winding: Winding) -> bool {
+    let det = p[0].x * (p[1].y * p[2].w - p[1].w * p[2].y)
+            - p[0].y * (p[1].x * p[2].w - p[1].w * p[2].x)
+            + p[0].w * (p[1].x * p[2].y - p[1].y * p[2].x);
+    match winding {
+        Winding::CounterClockwise => det < 0.0,
+        Winding::Clockwise => det > 0.0,
+    }
+}
@@ -0,0 +1,71 @@
+use sdl2::pixels::Color;
+
+/// How the fog thickens with the distance from the viewer
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FogMode {
+    /// Grows linearly from `start` to `end`, also known as depth cueing
+    Linear,
+    /// 1 - e^(-density * d)
+    Exponential,
+    /// 1 - e^(-(density * d)^2), stays clear longer and then closes in quickly
+    ExponentialSquared,
+}
+
+impl FogMode {
+    /// Cycles linear → exponential → exponential squared
+    pub fn next(&self) -> FogMode {
+        match self {
+            FogMode::Linear => FogMode::Exponential,
+            FogMode::Exponential => FogMode::ExponentialSquared,
+            FogMode::ExponentialSquared => FogMode::Linear,
+        }
+    }
+}
+
+/// Distance fog: shaded colours fade into `color` with the distance from the viewer.
+/// The colour should match the background, so far geometry disappears smoothly
+/// instead of being cut off by the far plane.
+#[derive(Copy, Clone, Debug)]
+pub struct Fog {
+    pub mode: FogMode,
+    pub color: Color,
+    /// Range of the linear fog, nothing is fogged before `start` and everything is after `end`
+    pub start: f32,
+    pub end: f32,
+    /// Thickness of the exponential fogs
+    pub density: f32
+}
+
+impl Fog {
+    /// Creates a fog that's fully opaque at `end` in every mode: the exponential
+    /// densities are picked so the remaining visibility there is under 1%
+    pub fn new(mode: FogMode, color: Color, start: f32, end: f32) -> Self {
+        let density = match mode {
+            FogMode::ExponentialSquared => 2.15 / end,
+            _ => 4.6 / end,
+        };
+        Fog { mode, color, start, end, density }
+    }
+
+    /// Returns how much of the surface colour remains at the distance: 1.0 is clear, 0.0 is fully fogged
+    pub fn factor(&self, distance: f32) -> f32 {
+        let f = match self.mode {
+            FogMode::Linear => (self.end - distance) / (self.end - self.start),
+            FogMode::Exponential => (-self.density * distance).exp(),
+            FogMode::ExponentialSquared => (-(self.density * distance).powi(2)).exp(),
+        };
+        f.clamp(0.0, 1.0)
+    }
+
+    /// Mixes a shaded colour with the fog colour by a factor returned from `factor`.
+    /// The alpha of the colour is kept.
+    pub fn blend(&self, color: Color, factor: f32) -> Color {
+        let mix = |c: u8, f: u8| (f as f32 + (c as f32 - f as f32) * factor) as u8;
+        Color::RGBA(mix(color.r, self.color.r), mix(color.g, self.color.g), mix(color.b, self.color.b), color.a)
+    }
+
+    /// Fogs a colour seen at the distance
+    pub fn apply(&self, color: Color, distance: f32) -> Color {
+        self.blend(color, self.factor(distance))
+    }
+}
@@ -0,0 +1,63 @@
+use super::bounds::Aabb;
+use super::matrix::Matrix;
+use super::vector::Vec3D;
+
+/// A plane `normal . p + d = 0`, the normal points to the positive side
+#[derive(Copy, Clone, Debug)]
+pub struct Plane {
+    pub normal: Vec3D,
+    pub d: f32
+}
+
+impl Plane {
+    /// Builds the plane from `a * x + b * y + c * z + d = 0`, normalized so the distances are in world units
+    fn new(a: f32, b: f32, c: f32, d: f32) -> Self {
+        let len = (a * a + b * b + c * c).sqrt().max(1e-12);
+        Plane { normal: Vec3D::new(a / len, b / len, c / len), d: d / len }
+    }
+
+    /// Signed distance of a point to the plane
+    pub fn distance(&self, p: &Vec3D) -> f32 {
+        self.normal.x * p.x + self.normal.y * p.y + self.normal.z * p.z + self.d
+    }
+}
+
+/// The volume seen by a camera, as six planes facing inwards: left, right, top, bottom, near and far.
+/// Used to skip whole objects before their triangles are transformed and clipped.
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+    pub planes: [Plane; 6]
+}
+
+impl Frustum {
+    /// Extracts the planes of a world (or model) to clip space matrix, e.g. `Camera::view_projection`.
+    /// With row vectors the clip coordinates are the dot products with the matrix columns,
+    /// so every plane of `clip::clip_triangle` is a sum of two columns (Gribb & Hartmann).
+    pub fn from_matrix(m: &Matrix) -> Self {
+        let v = &m.values;
+        let column = |c: usize| [v[0][c], v[1][c], v[2][c], v[3][c]];
+        let (x, y, z, w) = (column(0), column(1), column(2), column(3));
+        let plane = |f: &dyn Fn(usize) -> f32| Plane::new(f(0), f(1), f(2), f(3));
+        Frustum {
+            planes: [
+                plane(&|i| w[i] + x[i]), // left
+                plane(&|i| w[i] - x[i]), // right
+                plane(&|i| w[i] + y[i]), // top
+                plane(&|i| w[i] - y[i]), // bottom
+                plane(&|i| z[i]),        // near, depth goes from 0 to w
+                plane(&|i| w[i] - z[i]), // far
+            ]
+        }
+    }
+
+    /// False when the sphere is entirely outside of the frustum
+    pub fn intersects_sphere(&self, center: &Vec3D, radius: f32) -> bool {
+        self.planes.iter().all(|plane| plane.distance(center) >= -radius)
+    }
+
+    /// False when the box is entirely outside of one of the planes.
+    /// Conservative: a box near a frustum corner may pass without being visible.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| plane.distance(&aabb.support(&plane.normal)) >= 0.0)
+    }
+}
@@ -0,0 +1,110 @@
+use sdl2::pixels::Color;
+use super::vector::{self, Vec3D};
+
+/// Distance falloff of the point and spot lights: 1 / (constant + linear * d + quadratic * d^2)
+#[derive(Copy, Clone, Debug)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32
+}
+
+impl Attenuation {
+    /// A falloff that leaves roughly 1% of the intensity at `range`
+    pub fn range(range: f32) -> Self {
+        Attenuation { constant: 1.0, linear: 4.5 / range, quadratic: 75.0 / (range * range) }
+    }
+
+    pub fn factor(&self, distance: f32) -> f32 {
+        1.0 / (self.constant + self.linear * distance + self.quadratic * distance * distance)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum LightKind {
+    /// Lights everything evenly, scaled by `Material::ambient`
+    Ambient,
+    /// Parallel rays, `direction` points towards the light
+    Directional { direction: Vec3D },
+    /// Shines in every direction from `position`
+    Point { position: Vec3D, attenuation: Attenuation },
+    /// A cone from `position` along `direction`: full intensity up to `inner` degrees off the axis,
+    /// fading out towards `outer`
+    Spot { position: Vec3D, direction: Vec3D, inner: f32, outer: f32, attenuation: Attenuation },
+}
+
+/// A light source. Any number of them can light a scene, their contributions are summed.
+#[derive(Copy, Clone, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Color,
+    pub intensity: f32,
+    /// Whether the light casts shadows. The renderer has a single shadow map, it goes to the
+    /// first directional or spot light casting them (see `shadow::shadow_caster`).
+    pub casts_shadow: bool
+}
+
+impl Light {
+    pub fn new(kind: LightKind, color: Color, intensity: f32) -> Self {
+        Light { kind, color, intensity, casts_shadow: false }
+    }
+
+    pub fn ambient(color: Color, intensity: f32) -> Self {
+        Light::new(LightKind::Ambient, color, intensity)
+    }
+
+    pub fn directional(direction: Vec3D, color: Color, intensity: f32) -> Self {
+        let mut direction = direction;
+        direction.normalize();
+        Light::new(LightKind::Directional { direction }, color, intensity)
+    }
+
+    pub fn point(position: Vec3D, range: f32, color: Color, intensity: f32) -> Self {
+        Light::new(LightKind::Point { position, attenuation: Attenuation::range(range) }, color, intensity)
+    }
+
+    pub fn spot(position: Vec3D, direction: Vec3D, inner: f32, outer: f32, range: f32, color: Color, intensity: f32) -> Self {
+        let mut direction = direction;
+        direction.normalize();
+        let attenuation = Attenuation::range(range);
+        Light::new(LightKind::Spot { position, direction, inner, outer, attenuation }, color, intensity)
+    }
+
+    /// Colour times intensity, per channel in the 0..1 range
+    pub fn radiance(&self) -> [f32; 3] {
+        let c = |v: u8| v as f32 / 255.0 * self.intensity;
+        [c(self.color.r), c(self.color.g), c(self.color.b)]
+    }
+
+    /// Returns the normalized direction from a point towards the light and the fraction of
+    /// the light reaching it (distance and cone falloff), `None` for the ambient light
+    pub fn incidence(&self, point: &Vec3D) -> Option<(Vec3D, f32)> {
+        match self.kind {
+            LightKind::Ambient => None,
+            LightKind::Directional { direction } => Some((direction, 1.0)),
+            LightKind::Point { position, attenuation } => {
+                let (to_light, distance) = towards(point, &position);
+                Some((to_light, attenuation.factor(distance)))
+            },
+            LightKind::Spot { position, direction, inner, outer, attenuation } => {
+                let (to_light, distance) = towards(point, &position);
+                let cos_angle = -to_light.dot_product(&direction);
+                let (cos_inner, cos_outer) = (inner.to_radians().cos(), outer.to_radians().cos());
+                let t = ((cos_angle - cos_outer) / (cos_inner - cos_outer).max(1e-6)).clamp(0.0, 1.0);
+                let cone = t * t * (3.0 - 2.0 * t); // smoothstep
+                Some((to_light, attenuation.factor(distance) * cone))
+            },
+        }
+    }
+}
+
+/// Normalized direction from `point` to `position` and the distance between them
+fn towards(point: &Vec3D, position: &Vec3D) -> (Vec3D, f32) {
+    let to_light = *position - *point;
+    let distance = to_light.len();
+    if distance > 0.0 {
+        (vector::vec_div_by(&to_light, distance), distance)
+    } else {
+        (Vec3D::new(0.0, 1.0, 0.0), 0.0)
+    }
+}
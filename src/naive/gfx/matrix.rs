@@ -1,8 +1,10 @@
 use super::vector::{Vec3D, cross_product, vec_mul_by};
+use super::batch::VertexBatch;
 use std::ops::Mul;
 
 const UNIVERSAL_ARRAY_SIZE: usize = 4;
 
+#[derive(Copy, Clone, Debug)]
 pub struct Matrix {
     pub values: [[f32; UNIVERSAL_ARRAY_SIZE]; UNIVERSAL_ARRAY_SIZE] // 4x4 array
 }
@@ -48,15 +50,16 @@ impl Matrix {
         m
     }
 
-    pub fn init_rotation_x(rad_angle: f32) -> Self {
-        let mut rot_x = Matrix::init();
-        rot_x.values[0][0] = 1.0;
-        rot_x.values[1][1] = (rad_angle*0.5).cos() as f32;
-        rot_x.values[1][2] = (rad_angle*0.5).sin() as f32;
-        rot_x.values[2][1] = -(rad_angle*0.5).sin() as f32;
-        rot_x.values[2][2] = (rad_angle*0.5).cos() as f32;
-        rot_x.values[3][3] = 1.0;
-        rot_x
+    /// Parallel projection of a view `2 * half_height` units tall, the width follows from the aspect ratio
+    /// (height / width, like in `init_projection`). Depth from `near` to `far` is mapped to 0..1 and w stays 1.
+    pub fn init_orthographic(half_height: f32, aspect_ratio: f32, near: f32, far: f32) -> Self {
+        let mut m = Matrix::init();
+        m.values[0][0] = aspect_ratio / half_height;
+        m.values[1][1] = 1.0 / half_height;
+        m.values[2][2] = 1.0 / (far - near);
+        m.values[3][2] = -near / (far - near);
+        m.values[3][3] = 1.0;
+        m
     }
 
     pub fn init_rotation_y(rad_angle: f32) -> Self {
@@ -70,17 +73,6 @@ impl Matrix {
         rot_y
     }
 
-    pub fn init_rotation_z(rad_angle: f32) -> Self {
-        let mut rot_z = Matrix::init();
-        rot_z.values[0][0] = rad_angle.cos() as f32;
-        rot_z.values[0][1] = rad_angle.sin() as f32;
-        rot_z.values[1][0] = -rad_angle.sin() as f32;
-        rot_z.values[1][1] = rad_angle.cos() as f32;
-        rot_z.values[2][2] = 1.0;
-        rot_z.values[3][3] = 1.0;
-        rot_z
-    }
-
     pub fn init_translation(x: f32, y: f32, z: f32) -> Self {
         let mut translation = Matrix::init();
         translation.values[0][0] = 1.0;
@@ -93,6 +85,15 @@ impl Matrix {
         translation
     }
 
+    pub fn init_scale(x: f32, y: f32, z: f32) -> Self {
+        let mut scale = Matrix::init();
+        scale.values[0][0] = x;
+        scale.values[1][1] = y;
+        scale.values[2][2] = z;
+        scale.values[3][3] = 1.0;
+        scale
+    }
+
     pub fn point_at(pos: &Vec3D, target: &Vec3D, up: &Vec3D) -> Matrix {
         let mut forward: Vec3D = *target - *pos;
         forward.normalize();
@@ -123,6 +124,57 @@ impl Matrix {
         m
     }
 
+    /// The matrix for the normals of surfaces this one transforms: the inverse transpose of the
+    /// upper 3x3, so non-uniform scales keep the normals perpendicular. The normals have to be
+    /// normalized afterwards anyway, so it's the cofactor matrix without the division by the
+    /// determinant, only its sign is kept for mirroring transforms.
+    pub fn normal_matrix(&self) -> Matrix {
+        let row = |r: usize| [self.values[r][0], self.values[r][1], self.values[r][2]];
+        let cross = |a: [f32; 3], b: [f32; 3]| [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]];
+        let cofactors = [cross(row(1), row(2)), cross(row(2), row(0)), cross(row(0), row(1))];
+        let det: f32 = (0..3).map(|c| self.values[0][c] * cofactors[0][c]).sum();
+        let sign = if det < 0.0 { -1.0 } else { 1.0 };
+        let mut m = Matrix::init_identity();
+        for (row, cofactor) in m.values.iter_mut().zip(cofactors.iter()) {
+            for (value, c) in row.iter_mut().zip(cofactor.iter()) {
+                *value = sign * c;
+            }
+        }
+        m
+    }
+
+    /// General inverse (Gauss-Jordan elimination), `None` for a singular matrix.
+    /// Rigid transforms are cheaper to invert with `quick_inverse`.
+    pub fn inverse(&self) -> Option<Matrix> {
+        let mut a = self.values;
+        let mut inv = Matrix::init_identity().values;
+        for col in 0..UNIVERSAL_ARRAY_SIZE {
+            let pivot = (col..UNIVERSAL_ARRAY_SIZE)
+                .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let scale = 1.0 / a[col][col];
+            for k in 0..UNIVERSAL_ARRAY_SIZE {
+                a[col][k] *= scale;
+                inv[col][k] *= scale;
+            }
+            for row in 0..UNIVERSAL_ARRAY_SIZE {
+                let factor = a[row][col];
+                if row != col && factor != 0.0 {
+                    for k in 0..UNIVERSAL_ARRAY_SIZE {
+                        a[row][k] -= factor * a[col][k];
+                        inv[row][k] -= factor * inv[col][k];
+                    }
+                }
+            }
+        }
+        Some(Matrix { values: inv })
+    }
+
     pub fn apply(&self, input: &Vec3D) -> Vec3D {
         Vec3D {
             x: input.x * self.values[0][0] + input.y * self.values[1][0] + input.z * self.values[2][0] + input.w * self.values[3][0],
@@ -131,4 +183,14 @@ impl Matrix {
             w: input.x * self.values[0][3] + input.y * self.values[1][3] + input.z * self.values[2][3] + input.w * self.values[3][3]
         }
     }
+
+    /// Transforms a whole batch of vertices at once, using SIMD where available
+    pub fn apply_batch(&self, batch: &VertexBatch) -> VertexBatch {
+        batch.transform(self)
+    }
+
+    /// Combines the world, view and projection matrices, so a vertex is transformed only once
+    pub fn model_view_projection(world: &Matrix, view: &Matrix, projection: &Matrix) -> Matrix {
+        *world * *view * *projection
+    }
 }
@@ -1,34 +1,190 @@
 use super::triangle::Triangle;
+use super::batch::VertexBatch;
+use super::matrix::Matrix;
+use super::shading::{Material, BlendMode};
+use super::culling::{CullMode, Winding};
+use super::bounds::Aabb;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufReader, BufRead};
+use std::path::Path;
 use crate::naive::gfx::Vec3D;
 use sdl2::pixels::Color;
 
 pub struct Mesh {
-    pub tris: Vec<Triangle>
+    pub tris: Vec<Triangle>,
+    /// Shared vertex positions and normals, `faces[i]` indexes the vertices of `tris[i]`
+    pub positions: VertexBatch,
+    pub normals: VertexBatch,
+    pub faces: Vec<[usize; 3]>,
+    /// `Triangle::material` indexes this list, the first one is the default material
+    pub materials: Vec<Material>,
+    /// Box around the positions in model space
+    pub bounds: Aabb
 }
 
 impl Mesh {
-    pub fn load_object(file_name: &str) -> Self {
-        let file = File::open(file_name).unwrap();
+    /// Applies the face culling settings to every material of the mesh
+    pub fn set_culling(&mut self, cull: CullMode, winding: Winding) {
+        for material in self.materials.iter_mut() {
+            material.cull = cull;
+            material.winding = winding;
+        }
+    }
+
+    /// Returns a sphere (center, radius) enclosing the mesh placed by `transform`,
+    /// built around the transformed bounds so no vertex has to be transformed
+    pub fn bounding_sphere(&self, transform: &Matrix) -> (Vec3D, f32) {
+        self.bounds.transformed(transform).bounding_sphere()
+    }
+
+    /// Reads an OBJ file: vertex positions, faces (polygons are split into fans of triangles,
+    /// texture and normal indices are ignored) and the materials of its MTL libraries.
+    /// Other statements are skipped, malformed ones give an error naming the line.
+    pub fn load_object(file_name: &str) -> Result<Self, String> {
+        let file = File::open(file_name).map_err(|e| e.to_string())?;
         let mut vert: Vec<Vec3D> = Vec::new();
         let mut tris: Vec<Triangle> = Vec::new();
+        let mut faces: Vec<[usize; 3]> = Vec::new();
+        let mut materials = vec![Material::default()];
+        let mut names: HashMap<String, (usize, Color)> = HashMap::new();
+        let mut current: (usize, Option<Color>) = (0, None);
 
-        for l in BufReader::new(file).lines() {
-            let line = l.unwrap();
-            match line.get(0..1) {
+        for (number, l) in BufReader::new(file).lines().enumerate() {
+            let line = l.map_err(|e| e.to_string())?;
+            let error = |message: &str| format!("line {}: {}", number + 1, message);
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens.first().copied() {
                 Some("v") => {
-                    let coords: Vec<f32> = line.get(2..).unwrap().split(' ').map(|x| x.parse::<f32>().unwrap()).collect();
+                    let coords = tokens[1..].iter().map(|x| x.parse::<f32>()).collect::<Result<Vec<f32>, _>>()
+                        .map_err(|_| error("a vertex takes numbers"))?;
+                    if coords.len() < 3 {
+                        return Err(error("a vertex takes x, y and z"))
+                    }
                     vert.push(Vec3D::new(coords[0], coords[1], coords[2]));
                 },
                 Some("f") => {
-                    let idxs: Vec<usize> =  line.get(2..).unwrap().split(' ').map(|x| x.parse::<usize>().unwrap()).collect();
-                    tris.push(Triangle::new(vert[idxs[0]-1], vert[idxs[1]-1], vert[idxs[2]-1], None));
+                    // `v`, `v/vt`, `v//vn` or `v/vt/vn`, only the position index is used
+                    let idxs = tokens[1..].iter()
+                        .map(|x| match x.split('/').next().map(|i| i.parse::<usize>()) {
+                            Some(Ok(i)) if (1..=vert.len()).contains(&i) => Ok(i - 1),
+                            _ => Err(error(&format!("`{}` isn't a vertex declared before the face", x))),
+                        })
+                        .collect::<Result<Vec<usize>, String>>()?;
+                    if idxs.len() < 3 {
+                        return Err(error("a face takes at least three vertices"))
+                    }
+                    for i in 1..idxs.len() - 1 {
+                        let face = [idxs[0], idxs[i], idxs[i + 1]];
+                        let mut tri = Triangle::new(vert[face[0]], vert[face[1]], vert[face[2]], current.1);
+                        tri.material = current.0;
+                        tris.push(tri);
+                        faces.push(face);
+                    }
+                },
+                Some("mtllib") => {
+                    if tokens.len() < 2 {
+                        return Err(error("`mtllib` takes a file name"))
+                    }
+                    let dir = Path::new(file_name).parent().unwrap_or(Path::new(""));
+                    for (name, material, color) in load_materials(&dir.join(tokens[1..].join(" "))).map_err(|e| error(&e))? {
+                        names.insert(name, (materials.len(), color));
+                        materials.push(material);
+                    }
+                },
+                Some("usemtl") => {
+                    current = match names.get(&tokens[1..].join(" ")) {
+                        Some(&(idx, color)) => (idx, Some(color)),
+                        None => (0, None)
+                    };
                 },
                 _ => ()
             }
         }
 
-        Mesh {tris}
+        let normals = compute_vertex_normals(&mut tris, &faces, vert.len());
+        Ok(Mesh {
+            tris,
+            positions: VertexBatch::from_points(&vert),
+            normals: VertexBatch::from_points(&normals),
+            faces,
+            materials,
+            bounds: Aabb::from_points(&vert)
+        })
+    }
+}
+
+/// Reads the materials of an MTL library: (name, material, diffuse colour).
+/// A dissolve (`d`, or `Tr` = 1 - d) below 1 makes the material alpha blended.
+/// A missing library is skipped, so the mesh just uses the default material,
+/// one that can't be read is an error.
+fn load_materials(path: &Path) -> Result<Vec<(String, Material, Color)>, String> {
+    let mut result: Vec<(String, Material, Color)> = Vec::new();
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(_) => return Ok(result)
+    };
+    let to_byte = |v: f32| (v * 255.0).clamp(0.0, 255.0) as u8;
+
+    for l in BufReader::new(file).lines() {
+        let line = l.map_err(|e| format!("{}: {}", path.display(), e))?;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.is_empty() {
+            continue
+        }
+        if tokens[0] == "newmtl" {
+            result.push((tokens[1..].join(" "), Material::default(), Color::RGBA(255,255,255,255)));
+            continue
+        }
+        let values: Vec<f32> = tokens[1..].iter().filter_map(|x| x.parse::<f32>().ok()).collect();
+        let (material, color) = match result.last_mut() {
+            Some((_, m, c)) if !values.is_empty() => (m, c),
+            _ => continue
+        };
+        let average = values.iter().sum::<f32>() / values.len() as f32;
+        match tokens[0] {
+            "Kd" if values.len() >= 3 => {
+                color.r = to_byte(values[0]);
+                color.g = to_byte(values[1]);
+                color.b = to_byte(values[2]);
+            },
+            "Ka" => material.ambient = average,
+            "Ks" => material.specular = average,
+            "Ns" => material.shininess = values[0],
+            "d" => material.opacity = values[0],
+            "Tr" => material.opacity = 1.0 - values[0],
+            _ => ()
+        }
+    }
+
+    for (_, material, _) in result.iter_mut() {
+        if material.opacity < 1.0 {
+            material.blend = BlendMode::Alpha;
+        }
+    }
+    Ok(result)
+}
+
+/// Averages the face normals around every shared vertex so the lighting can be
+/// evaluated per vertex (Gouraud shading)
+fn compute_vertex_normals(tris: &mut [Triangle], faces: &[[usize; 3]], vert_count: usize) -> Vec<Vec3D> {
+    let mut normals = vec![Vec3D::new(0.0, 0.0, 0.0); vert_count];
+    for (tri, face) in tris.iter().zip(faces.iter()) {
+        let normal = tri.face_normal();
+        for &idx in face {
+            normals[idx] += normal;
+        }
+    }
+    for n in normals.iter_mut() {
+        if n.len() > 0.0 {
+            n.normalize();
+        }
+        n.w = 0.0;
+    }
+    for (tri, face) in tris.iter_mut().zip(faces.iter()) {
+        for i in 0..3 {
+            tri.n[i] = normals[face[i]];
+        }
     }
+    normals
 }
\ No newline at end of file
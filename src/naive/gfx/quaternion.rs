@@ -0,0 +1,109 @@
+use std::ops::Mul;
+use super::matrix::Matrix;
+use super::vector::Vec3D;
+
+/// A rotation as a unit quaternion: w + xi + yj + zk
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32
+}
+
+/// Hamilton product: `a * b` rotates by `b` first and then by `a`
+impl Mul for Quaternion {
+    type Output = Quaternion;
+    fn mul(self, q: Quaternion) -> Quaternion {
+        Quaternion {
+            w: self.w * q.w - self.x * q.x - self.y * q.y - self.z * q.z,
+            x: self.w * q.x + self.x * q.w + self.y * q.z - self.z * q.y,
+            y: self.w * q.y - self.x * q.z + self.y * q.w + self.z * q.x,
+            z: self.w * q.z + self.x * q.y - self.y * q.x + self.z * q.w,
+        }
+    }
+}
+
+impl Quaternion {
+    pub fn identity() -> Self {
+        Quaternion { w: 1.0, x: 0.0, y: 0.0, z: 0.0 }
+    }
+
+    /// Rotation by `angle` radians around the axis
+    pub fn from_axis_angle(axis: &Vec3D, angle: f32) -> Self {
+        let mut axis = *axis;
+        axis.normalize();
+        let (s, c) = (angle * 0.5).sin_cos();
+        Quaternion { w: c, x: axis.x * s, y: axis.y * s, z: axis.z * s }
+    }
+
+    /// Rotation by euler angles in radians, applied around x, then y and then z
+    pub fn from_euler(x: f32, y: f32, z: f32) -> Self {
+        let qx = Quaternion::from_axis_angle(&Vec3D::new(1.0, 0.0, 0.0), x);
+        let qy = Quaternion::from_axis_angle(&Vec3D::new(0.0, 1.0, 0.0), y);
+        let qz = Quaternion::from_axis_angle(&Vec3D::new(0.0, 0.0, 1.0), z);
+        qz * qy * qx
+    }
+
+    pub fn len(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn dot(&self, q: &Quaternion) -> f32 {
+        self.w * q.w + self.x * q.x + self.y * q.y + self.z * q.z
+    }
+
+    pub fn normalized(&self) -> Quaternion {
+        let l = self.len();
+        if l == 0.0 {
+            return Quaternion::identity()
+        }
+        Quaternion { w: self.w / l, x: self.x / l, y: self.y / l, z: self.z / l }
+    }
+
+    /// The same rotation with all components negated, `q` and `-q` rotate alike
+    pub fn negated(&self) -> Quaternion {
+        Quaternion { w: -self.w, x: -self.x, y: -self.y, z: -self.z }
+    }
+
+    /// Spherical linear interpolation from `self` (t = 0) to `q` (t = 1) at a constant angular
+    /// speed, along the shorter arc
+    pub fn slerp(&self, q: &Quaternion, t: f32) -> Quaternion {
+        let (a, mut b) = (self.normalized(), q.normalized());
+        let mut cos = a.dot(&b);
+        if cos < 0.0 {
+            b = b.negated();
+            cos = -cos;
+        }
+        // nearly the same rotation: the sine below gets unstable, a normalized lerp is as good
+        let (wa, wb) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let angle = cos.acos();
+            let sin = angle.sin();
+            (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+        };
+        Quaternion {
+            w: a.w * wa + b.w * wb,
+            x: a.x * wa + b.x * wb,
+            y: a.y * wa + b.y * wb,
+            z: a.z * wa + b.z * wb
+        }.normalized()
+    }
+
+    /// Rotation matrix for row vectors (`v * M`, like the rest of the matrices)
+    pub fn to_matrix(self) -> Matrix {
+        let Quaternion { w, x, y, z } = self.normalized();
+        let mut m = Matrix::init_identity();
+        m.values[0][0] = 1.0 - 2.0 * (y * y + z * z);
+        m.values[0][1] = 2.0 * (x * y + w * z);
+        m.values[0][2] = 2.0 * (x * z - w * y);
+        m.values[1][0] = 2.0 * (x * y - w * z);
+        m.values[1][1] = 1.0 - 2.0 * (x * x + z * z);
+        m.values[1][2] = 2.0 * (y * z + w * x);
+        m.values[2][0] = 2.0 * (x * z + w * y);
+        m.values[2][1] = 2.0 * (y * z - w * x);
+        m.values[2][2] = 1.0 - 2.0 * (x * x + y * y);
+        m
+    }
+}
@@ -0,0 +1,117 @@
+use sdl2::pixels::Color;
+use super::matrix::Matrix;
+use super::mesh::Mesh;
+use super::vector::Vec3D;
+use super::varying::{Varyings, VAR_NORMAL, VAR_WORLD};
+
+/// A mesh vertex handed to the vertex stage, in object space
+pub struct VertexInput {
+    pub position: Vec3D,
+    pub normal: Vec3D
+}
+
+/// Per-vertex program. It runs once for every shared vertex of a mesh and returns the
+/// clip space position (before the perspective divide), anything the fragment stage needs
+/// goes into the varyings: the slot layout is up to the shader pair.
+pub trait VertexShader: Sync {
+    fn vertex(&self, input: &VertexInput, out: &mut Varyings) -> Vec3D;
+}
+
+/// Per-pixel program: receives the varyings of the vertex stage interpolated
+/// perspective-correctly across the triangle and returns the pixel colour.
+pub trait FragmentShader: Send + Sync {
+    fn fragment(&self, var: &Varyings) -> Color;
+}
+
+/// Runs the vertex shader over every vertex of the mesh: (clip position, varyings)
+pub fn run_vertex_shader<V: VertexShader + ?Sized>(shader: &V, mesh: &Mesh, index: usize) -> (Vec3D, Varyings) {
+    let input = VertexInput {
+        position: mesh.positions.get(index),
+        normal: mesh.normals.get(index)
+    };
+    let mut var = Varyings::init();
+    let position = shader.vertex(&input, &mut var);
+    (position, var)
+}
+
+/// A general purpose vertex shader: stores the world space position and normal
+/// (`VAR_WORLD`, `VAR_NORMAL`) and a white colour (`VAR_COLOR`)
+pub struct WorldVertexShader {
+    pub world: Matrix,
+    /// Transforms the normals, see `Matrix::normal_matrix`
+    pub normal: Matrix,
+    pub mvp: Matrix
+}
+
+impl WorldVertexShader {
+    pub fn new(world: &Matrix, view_projection: &Matrix) -> Self {
+        WorldVertexShader { world: *world, normal: world.normal_matrix(), mvp: *world * *view_projection }
+    }
+}
+
+impl VertexShader for WorldVertexShader {
+    fn vertex(&self, input: &VertexInput, out: &mut Varyings) -> Vec3D {
+        out.set_color(Color::RGBA(255, 255, 255, 255));
+        let mut normal = self.normal.apply(&input.normal);
+        if normal.len() > 0.0 {
+            normal.normalize();
+        }
+        out.set_vec(VAR_NORMAL, &normal);
+        out.set_vec(VAR_WORLD, &self.world.apply(&input.position));
+        self.mvp.apply(&input.position)
+    }
+}
+
+/// Colours the surface by its world space height, from `low` at `min` to `high` at `max`
+pub struct HeightColor {
+    pub min: f32,
+    pub max: f32,
+    pub low: Color,
+    pub high: Color
+}
+
+impl FragmentShader for HeightColor {
+    fn fragment(&self, var: &Varyings) -> Color {
+        let t = ((var.data[VAR_WORLD + 1] - self.min) / (self.max - self.min)).clamp(0.0, 1.0);
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t) as u8;
+        Color::RGBA(mix(self.low.r, self.high.r), mix(self.low.g, self.high.g), mix(self.low.b, self.high.b), 255)
+    }
+}
+
+/// Cel shading: the diffuse term is quantized into a few flat bands
+pub struct Toon {
+    pub light_dir: Vec3D,
+    pub bands: u32,
+    pub color: Color
+}
+
+impl FragmentShader for Toon {
+    fn fragment(&self, var: &Varyings) -> Color {
+        let mut normal = var.vec(VAR_NORMAL);
+        normal.normalize();
+        let diffuse = normal.dot_product(&self.light_dir).max(0.0);
+        let bands = self.bands.max(1) as f32;
+        let level = 0.2 + 0.8 * ((diffuse * bands).ceil() / bands);
+        let c = |v: u8| (v as f32 * level).min(255.0) as u8;
+        Color::RGBA(c(self.color.r), c(self.color.g), c(self.color.b), 255)
+    }
+}
+
+/// Debug view of the interpolated normals, mapped from -1..1 to 0..255
+pub struct NormalView;
+
+impl FragmentShader for NormalView {
+    fn fragment(&self, var: &Varyings) -> Color {
+        let mut normal = var.vec(VAR_NORMAL);
+        normal.normalize();
+        let c = |v: f32| ((v * 0.5 + 0.5) * 255.0).clamp(0.0, 255.0) as u8;
+        Color::RGBA(c(normal.x), c(normal.y), c(normal.z), 255)
+    }
+}
+
+/// Returns the lowest and highest world space height of a mesh, handy for `HeightColor`.
+/// Taken from the transformed bounding box, so a rotated mesh may get a slightly wider range.
+pub fn height_range(mesh: &Mesh, world: &Matrix) -> (f32, f32) {
+    let bounds = mesh.bounds.transformed(world);
+    (bounds.min.y, bounds.max.y)
+}
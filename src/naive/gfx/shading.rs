@@ -0,0 +1,158 @@
+use sdl2::pixels::Color;
+use super::vector::{self, Vec3D};
+use super::varying::{Varyings, VAR_NORMAL, VAR_WORLD};
+use super::culling::{CullMode, Winding};
+use super::light::Light;
+
+/// Defines how the lighting gets evaluated for a triangle
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ShadingMode {
+    /// A single luminance per face, painted with one colour
+    Flat,
+    /// Lighting is evaluated per vertex and colours are interpolated across the face
+    Gouraud,
+    /// Per-pixel lighting with interpolated normals and a reflected-ray specular term
+    Phong,
+    /// Per-pixel lighting with a half-vector specular term
+    BlinnPhong,
+}
+
+impl ShadingMode {
+    /// Cycles Flat → Gouraud → Phong → Blinn-Phong
+    pub fn next(&self) -> ShadingMode {
+        match self {
+            ShadingMode::Flat => ShadingMode::Gouraud,
+            ShadingMode::Gouraud => ShadingMode::Phong,
+            ShadingMode::Phong => ShadingMode::BlinnPhong,
+            ShadingMode::BlinnPhong => ShadingMode::Flat,
+        }
+    }
+}
+
+/// How a shaded colour gets combined with the colour already in the frame buffer
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BlendMode {
+    /// Replaces the destination
+    Opaque,
+    /// src * a + dst * (1 - a)
+    Alpha,
+    /// dst + src * a
+    Additive,
+    /// dst * src, faded by the source alpha
+    Multiply,
+}
+
+/// The lights of a frame and where they are seen from, the same for every shaded point
+#[derive(Copy, Clone)]
+pub struct Lighting<'a> {
+    pub lights: &'a [Light],
+    /// Position of the viewer
+    pub eye: Vec3D,
+    /// Half-vector specular (Blinn-Phong) instead of the reflected ray (Phong)
+    pub blinn: bool,
+    /// Index of the light the shadow map belongs to
+    pub shadow_caster: Option<usize>
+}
+
+/// Surface reflection properties
+#[derive(Copy, Clone, Debug)]
+pub struct Material {
+    pub ambient: f32,
+    pub diffuse: f32,
+    pub specular: f32,
+    pub shininess: f32,
+    /// 1.0 is fully opaque, multiplies the alpha of the shaded colour
+    pub opacity: f32,
+    pub blend: BlendMode,
+    pub cull: CullMode,
+    pub winding: Winding
+}
+
+impl Material {
+    pub fn new(ambient: f32, diffuse: f32, specular: f32, shininess: f32) -> Self {
+        Material {
+            ambient, diffuse, specular, shininess,
+            opacity: 1.0,
+            blend: BlendMode::Opaque,
+            cull: CullMode::Back,
+            winding: Winding::CounterClockwise
+        }
+    }
+
+    pub fn default() -> Self {
+        Material::new(0.1, 0.9, 0.5, 32.0)
+    }
+
+    /// Transparent materials are drawn after the opaque ones and don't write depth
+    pub fn is_transparent(&self) -> bool {
+        self.blend != BlendMode::Opaque
+    }
+
+    /// Returns (diffuse, specular) intensities of a surface point lit from one direction.
+    /// All of the vectors are expected to be normalized, `light_dir` points towards the light
+    /// and `view_dir` towards the viewer.
+    pub fn illuminate(&self, normal: &Vec3D, light_dir: &Vec3D, view_dir: &Vec3D, blinn: bool) -> (f32, f32) {
+        let n_dot_l = normal.dot_product(light_dir);
+        if n_dot_l <= 0.0 {
+            return (0.0, 0.0)
+        }
+
+        let spec_angle = if blinn {
+            let mut half = *light_dir + *view_dir;
+            half.normalize();
+            normal.dot_product(&half)
+        } else {
+            // reflect the light direction around the normal: 2(n.l)n - l
+            let reflected = vector::vec_mul_by(normal, 2.0 * n_dot_l) - *light_dir;
+            reflected.dot_product(view_dir)
+        };
+        (self.diffuse * n_dot_l, self.specular * spec_angle.max(0.0).powf(self.shininess))
+    }
+
+    /// Lights a surface point of the given albedo with all of the lights and returns the resulting colour.
+    /// `shadow` is the lit fraction of the point (1.0 unless it's in a shadow), it scales the
+    /// light casting the shadow map.
+    pub fn shade(&self, albedo: Color, point: &Vec3D, normal: &Vec3D, lighting: &Lighting, shadow: f32) -> Color {
+        let mut view_dir = lighting.eye - *point;
+        view_dir.normalize();
+        let mut diffuse = [0.0; 3];
+        let mut specular = [0.0; 3];
+        for (i, light) in lighting.lights.iter().enumerate() {
+            let radiance = light.radiance();
+            match light.incidence(point) {
+                None => {
+                    for c in 0..3 {
+                        diffuse[c] += self.ambient * radiance[c];
+                    }
+                },
+                Some((light_dir, falloff)) => {
+                    let visibility = if lighting.shadow_caster == Some(i) { shadow } else { 1.0 };
+                    if falloff * visibility <= 0.0 {
+                        continue
+                    }
+                    let (d, s) = self.illuminate(normal, &light_dir, &view_dir, lighting.blinn);
+                    for c in 0..3 {
+                        diffuse[c] += d * radiance[c] * falloff * visibility;
+                        specular[c] += s * radiance[c] * falloff * visibility;
+                    }
+                },
+            }
+        }
+        let mut color = apply_light(albedo, diffuse, specular);
+        color.a = (color.a as f32 * self.opacity).clamp(0.0, 255.0) as u8;
+        color
+    }
+
+    /// Per-fragment lighting: reads the interpolated normal, world position and colour
+    pub fn shade_fragment(&self, var: &Varyings, lighting: &Lighting, shadow: f32) -> Color {
+        let mut normal = var.vec(VAR_NORMAL);
+        normal.normalize();
+        self.shade(var.color(), &var.vec(VAR_WORLD), &normal, lighting, shadow)
+    }
+}
+
+/// Scales the albedo by the diffuse light and adds the specular highlight on top, per channel
+pub fn apply_light(albedo: Color, diffuse: [f32; 3], specular: [f32; 3]) -> Color {
+    let c = |v: u8, i: usize| (v as f32 * diffuse[i] + 255.0 * specular[i]).clamp(0.0, 255.0) as u8;
+    Color::RGBA(c(albedo.r, 0), c(albedo.g, 1), c(albedo.b, 2), albedo.a)
+}
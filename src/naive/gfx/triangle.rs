@@ -1,12 +1,15 @@
-use super::{matrix::Matrix, vector::Vec3D};
+use super::{vector::Vec3D, shading::{Lighting, Material}};
+use super::varying::{Varyings, VAR_NORMAL, VAR_WORLD};
 use sdl2::pixels::Color;
 use std::cmp::max;
 use super::vector;
-use crate::naive::render::Window;
 
 #[derive(Copy, Clone, Debug)]
 pub struct Triangle {
     pub p: [Vec3D; 3],
+    pub n: [Vec3D; 3], // vertex normals
+    pub var: [Varyings; 3],
+    pub material: usize, // index into the materials of the mesh
     pub base_color: Color,
     pub color: Color
 }
@@ -18,115 +21,91 @@ impl Triangle {
             Some(c) => c,
             None => Color::RGBA(255,255,255,255)
         };
-        Triangle {p: [p1, p2, p3], base_color: color, color}
-    }
-
-    // Returns an array of 2 tuples: i32 for X,Y
-    pub fn get_2d_points(&self) -> [(i32, i32);3] {
-        [(self.p[0].x as i32, self.p[0].y as i32),
-            (self.p[1].x as i32, self.p[1].y as i32),
-            (self.p[2].x as i32, self.p[2].y as i32)]
+        let mut tri = Triangle {
+            p: [p1, p2, p3],
+            n: [Vec3D::init(); 3],
+            var: [Varyings::from_color(color); 3],
+            material: 0,
+            base_color: color,
+            color
+        };
+        let normal = tri.face_normal();
+        tri.n = [normal; 3];
+        tri
     }
 
-    /// Returns an array of 3 tuples: i32 for X,Y,Z
-    pub fn get_3d_points(&self) -> [(f32,f32,f32); 3] {
-        [
-            (self.p[0].x, self.p[0].y, self.p[0].z),
-            (self.p[1].x, self.p[1].y, self.p[1].z),
-            (self.p[2].x, self.p[2].y, self.p[2].z)]
+    /// Returns a normalized face normal. Its `w` is 0, so translations don't affect it
+    pub fn face_normal(&self) -> Vec3D {
+        let line1 = self.p[1] - self.p[0];
+        let line2 = self.p[2] - self.p[0];
+        let mut normal = vector::cross_product(&line1, &line2);
+        if normal.len() > 0.0 {
+            normal.normalize();
+        }
+        normal.w = 0.0;
+        normal
     }
 
+    /// Perspective divide. The reciprocal of the original `w` is kept in `w`,
+    /// so the rasterizer can interpolate varyings perspective-correctly.
     pub fn normalize(&mut self) {
-        self.p[0] = vector::vec_div_by(&self.p[0], self.p[0].w);
-        self.p[1] = vector::vec_div_by(&self.p[1], self.p[1].w);
-        self.p[2] = vector::vec_div_by(&self.p[2], self.p[2].w);
+        for i in 0..3 {
+            let w = self.p[i].w;
+            self.p[i] = vector::vec_div_by(&self.p[i], w);
+            self.p[i].w = 1.0 / w;
+        }
     }
 
-    pub fn shade(&mut self, luminance: f32) {
-        self.color.r = (self.base_color.r as f32*luminance) as u8;
-        self.color.g = (self.base_color.g as f32*luminance) as u8;
-        self.color.b = (self.base_color.b as f32*luminance) as u8;
+    /// Maps a normalized triangle from NDC to pixel coordinates of a `width` x `height` target
+    pub fn map_to_viewport(&mut self, width: f32, height: f32) {
+        for p in self.p.iter_mut() {
+            p.x = (p.x + 1.0) * 0.5 * width;
+            p.y = (p.y + 1.0) * 0.5 * height;
+        }
     }
 
-    /// Add value to each point
-    pub fn add_each_point(&mut self, p: Vec3D) {
-        self.p[0] += p;
-        self.p[1] += p;
-        self.p[2] += p;
+    /// Returns the centroid of the triangle
+    pub fn centroid(&self) -> Vec3D {
+        vector::vec_div_by(&(self.p[0] + self.p[1] + self.p[2]), 3.0)
     }
 
-    pub fn draw_fast(&self, window: &mut Window, fill: bool) {
-        let points = self.get_2d_points();
-        if fill {
-            window.fill_triangle(self.color, points[0], points[1], points[2]).unwrap();
-        }
-        window.draw_triangle_fast(self.color,
-                                  (self.p[0].x as i16, self.p[0].y as i16),
-                                  (self.p[1].x as i16, self.p[1].y as i16),
-                                  (self.p[2].x as i16, self.p[2].y as i16)).unwrap();
+    /// Lights the whole face at its centroid and paints it with a single colour.
+    /// `shadow` returns the lit fraction of a point, see `Material::shade`
+    pub fn shade<V>(&mut self, material: &Material, lighting: &Lighting, shadow: V)
+        where V: Fn(&Vec3D) -> f32 {
+        let centroid = self.centroid();
+        self.color = material.shade(self.base_color, &centroid, &self.face_normal(), lighting, shadow(&centroid));
     }
 
-    /// Creates a new Triangle instance from matrix application to the given triangle
-    pub fn from_matrix_application(m: &Matrix, origin: &Triangle) -> Self {
-        Triangle {
-            p: [m.apply(&origin.p[0]), m.apply(&origin.p[1]), m.apply(&origin.p[2])],
-            base_color: origin.base_color,
-            color: origin.color,
+    /// Evaluates lighting at each vertex using its normal and stores the colours in the varyings
+    pub fn shade_vertices<V>(&mut self, material: &Material, lighting: &Lighting, shadow: V)
+        where V: Fn(&Vec3D) -> f32 {
+        for i in 0..3 {
+            let color = material.shade(self.base_color, &self.p[i], &self.n[i], lighting, shadow(&self.p[i]));
+            self.var[i].set_color(color);
         }
     }
-}
-
-/// Checks if a triangle is clipping against the plane and returns its decomposition
-pub fn clipping(plane_point: &Vec3D, plane_normal: &mut Vec3D, triangle: &Triangle) -> [Option<Triangle>;2] {
-    plane_normal.normalize();
-    let dist = |mut p: Vec3D| { // a helping lambda function to acquire distances
-        plane_normal.dot_product(&p) - plane_normal.dot_product(plane_point)
-    };
 
-    let mut in_points: [Vec3D;3] = [Vec3D::init();3];
-    let mut out_points:[Vec3D;3] = [Vec3D::init();3];
-    let mut in_count = 0;
-    let mut out_count = 0;
-    let distances = [dist(triangle.p[0]), dist(triangle.p[1]), dist(triangle.p[2])];
-
-    for i in 0..distances.len() {
-        if distances[i] > 0.0 {
-            in_points[in_count] = triangle.p[i];
-            in_count += 1;
-        } else {
-            out_points[out_count] = triangle.p[i];
-            out_count += 1;
+    /// Stores the albedo, world position and normal of each vertex in the varyings,
+    /// so the lighting can be evaluated per pixel
+    pub fn store_surface(&mut self) {
+        for i in 0..3 {
+            self.var[i].set_color(self.base_color);
+            self.var[i].set_vec(VAR_NORMAL, &self.n[i]);
+            self.var[i].set_vec(VAR_WORLD, &self.p[i]);
         }
     }
 
-    // Classification
-    return if in_count == 3 {
-        // All points lie on the inside of plane, so do nothing
-        // and allow the triangle to simply pass through
-        [Some(*triangle), None]
-    } else if in_count == 1 && out_count == 2 {
-        let out_triangle =
-            Triangle::new(in_points[0],
-                          vector::intersect_plane(*plane_point, plane_normal, in_points[0], out_points[0]),
-                          vector::intersect_plane(*plane_point, plane_normal, in_points[0], out_points[1]),
-                          Some(triangle.color));
-
-        return [Some(out_triangle), None]
-    } else if in_count == 2 && out_count == 1 {
-        let out_triangle1 =
-            Triangle::new(in_points[0],
-                          in_points[1],
-                          vector::intersect_plane(*plane_point, plane_normal, in_points[0], out_points[0]),
-                          Some(triangle.color));
-
-        let out_triangle2 =
-            Triangle::new(in_points[1],
-                          out_triangle1.p[2],
-                          vector::intersect_plane(*plane_point, plane_normal, in_points[1], out_points[0]),
-                          Some(triangle.color));
-        return [Some(out_triangle1), Some(out_triangle2)]
-    } else {  // in_count == 0
-        // Triangle is beyond the plane, so cut it entirely
-        return [None, None]
+    /// Turns the triangle around: reverses the vertex order and negates the normals,
+    /// so a back face can be lit as a front one (two-sided lighting)
+    pub fn flip(&mut self) {
+        self.p.swap(1, 2);
+        self.n.swap(1, 2);
+        self.var.swap(1, 2);
+        for n in self.n.iter_mut() {
+            n.x = -n.x;
+            n.y = -n.y;
+            n.z = -n.z;
+        }
     }
-}
\ No newline at end of file
+}
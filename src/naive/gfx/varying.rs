@@ -0,0 +1,76 @@
+use sdl2::pixels::Color;
+use super::vector::Vec3D;
+
+/// Amount of float slots every vertex carries into the rasterizer
+pub const MAX_VARYINGS: usize = 16;
+
+/// Slot of the vertex colour: r,g,b,a in the 0..255 range
+pub const VAR_COLOR: usize = 0;
+/// Slot of the world space normal: x,y,z
+pub const VAR_NORMAL: usize = 4;
+/// Slot of the world space position: x,y,z
+pub const VAR_WORLD: usize = 7;
+/// Slot of the fog factor evaluated per vertex, see `Fog::factor`
+pub const VAR_FOG: usize = 10;
+
+/// Per-vertex attributes that get interpolated across a triangle.
+#[derive(Copy, Clone, Debug)]
+pub struct Varyings {
+    pub data: [f32; MAX_VARYINGS]
+}
+
+impl Varyings {
+    pub fn init() -> Self {
+        Varyings { data: [0.0; MAX_VARYINGS] }
+    }
+
+    pub fn from_color(color: Color) -> Self {
+        let mut v = Varyings::init();
+        v.set_color(color);
+        v
+    }
+
+    pub fn set_color(&mut self, color: Color) {
+        self.data[VAR_COLOR] = color.r as f32;
+        self.data[VAR_COLOR + 1] = color.g as f32;
+        self.data[VAR_COLOR + 2] = color.b as f32;
+        self.data[VAR_COLOR + 3] = color.a as f32;
+    }
+
+    /// Reads the colour slots back, clamping every channel
+    pub fn color(&self) -> Color {
+        let c = |v: f32| v.clamp(0.0, 255.0) as u8;
+        Color::RGBA(c(self.data[VAR_COLOR]), c(self.data[VAR_COLOR + 1]),
+                    c(self.data[VAR_COLOR + 2]), c(self.data[VAR_COLOR + 3]))
+    }
+
+    /// Stores x,y,z of a vector starting at the given slot
+    pub fn set_vec(&mut self, slot: usize, v: &Vec3D) {
+        self.data[slot] = v.x;
+        self.data[slot + 1] = v.y;
+        self.data[slot + 2] = v.z;
+    }
+
+    /// Reads a vector starting at the given slot
+    pub fn vec(&self, slot: usize) -> Vec3D {
+        Vec3D::new(self.data[slot], self.data[slot + 1], self.data[slot + 2])
+    }
+
+    /// Linear interpolation between `a` and `b`: a + (b - a) * t
+    pub fn lerp(a: &Varyings, b: &Varyings, t: f32) -> Varyings {
+        let mut v = Varyings::init();
+        for i in 0..MAX_VARYINGS {
+            v.data[i] = a.data[i] + (b.data[i] - a.data[i]) * t;
+        }
+        v
+    }
+
+    /// Weighted sum of three varyings, used with barycentric weights
+    pub fn weighted(v: &[Varyings; 3], w: [f32; 3]) -> Varyings {
+        let mut out = Varyings::init();
+        for i in 0..MAX_VARYINGS {
+            out.data[i] = v[0].data[i] * w[0] + v[1].data[i] * w[1] + v[2].data[i] * w[2];
+        }
+        out
+    }
+}
@@ -76,12 +76,14 @@ pub fn cross_product(p: &Vec3D, other: &Vec3D) -> Vec3D {
     }
 }
 
-pub fn intersect_plane(plane_point: Vec3D, plane_normal: &mut Vec3D, ray_start: Vec3D, ray_finish: Vec3D) -> Vec3D {
-    plane_normal.normalize();
-    let a = ray_start.dot_product(plane_normal);
-    let b = ray_finish.dot_product(plane_normal);
-    let t = (plane_normal.dot_product(&plane_point) - a)/(b - a);
-    ray_start + vec_mul_by(&(ray_finish - ray_start), t)
+/// Linear interpolation between two vectors, including the `w` component
+pub fn vec_lerp(a: &Vec3D, b: &Vec3D, t: f32) -> Vec3D {
+    Vec3D {
+        x: a.x + (b.x - a.x) * t,
+        y: a.y + (b.y - a.y) * t,
+        z: a.z + (b.z - a.z) * t,
+        w: a.w + (b.w - a.w) * t,
+    }
 }
 
 impl Vec3D {
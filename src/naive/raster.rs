@@ -0,0 +1,480 @@
+use sdl2::pixels::Color;
+use super::gfx::{Triangle, Varyings};
+use super::gfx::shading::BlendMode;
+
+/// Anti-aliasing of a frame buffer, chosen when it's created
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AntiAliasing {
+    None,
+    /// Multi-sampling: 2, 4 or 8 coverage samples per pixel, shaded once per pixel
+    Msaa(u32),
+    /// Renders at `factor` times the resolution on both axes and box-filters it down
+    Supersample(u32),
+}
+
+/// Defines what gets drawn for every triangle
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RenderMode {
+    Solid,
+    /// Solid faces with their edges drawn on top
+    SolidWireframe,
+    /// Edges only, nothing gets occluded
+    Wireframe,
+    /// Edges of the visible faces only: faces are filled with the background colour
+    HiddenLine,
+    /// Vertices only
+    Points,
+}
+
+impl RenderMode {
+    /// Cycles solid → solid with wireframe → wireframe → hidden line → points
+    pub fn next(&self) -> RenderMode {
+        match self {
+            RenderMode::Solid => RenderMode::SolidWireframe,
+            RenderMode::SolidWireframe => RenderMode::Wireframe,
+            RenderMode::Wireframe => RenderMode::HiddenLine,
+            RenderMode::HiddenLine => RenderMode::Points,
+            RenderMode::Points => RenderMode::Solid,
+        }
+    }
+}
+
+/// Looks of the lines and points drawn by the wireframe render modes
+#[derive(Copy, Clone, Debug)]
+pub struct WireStyle {
+    pub color: Color,
+    /// Line width and point size in target pixels
+    pub thickness: u32,
+    /// Fill colour of the faces in the hidden-line mode, should match the background
+    pub background: Color
+}
+
+/// Sub-pixel sample positions (in 1/16 of a pixel) for every supported sample count
+fn sample_pattern(samples: usize) -> &'static [(i64, i64)] {
+    match samples {
+        2 => &[(4, 4), (12, 12)],
+        4 => &[(6, 2), (14, 6), (2, 10), (10, 14)],
+        8 => &[(9, 5), (7, 11), (13, 9), (5, 3), (3, 13), (1, 7), (11, 15), (15, 1)],
+        _ => &[(8, 8)],
+    }
+}
+
+/// A software colour buffer the triangles are rasterized into.
+/// Pixels are stored as packed ARGB8888 values.
+///
+/// Triangles are drawn into `pixels`, the render target: it's `target_width` x `target_height`
+/// pixels with `samples` consecutive values each, `depth` keeps the NDC depth of every sample.
+/// `resolve` turns the target into the `width` x `height` image returned by `output`.
+pub struct FrameBuffer {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u32>,
+    pub depth: Vec<f32>,
+    pub anti_aliasing: AntiAliasing,
+    resolved: Vec<u32>
+}
+
+/// Packs a colour into an ARGB8888 value
+pub fn pack_color(color: Color) -> u32 {
+    (color.a as u32) << 24 | (color.r as u32) << 16 | (color.g as u32) << 8 | color.b as u32
+}
+
+/// Combines a source colour with a packed destination value
+pub fn blend_color(mode: BlendMode, src: Color, dst: u32) -> u32 {
+    if mode == BlendMode::Opaque {
+        return pack_color(src)
+    }
+    let a = src.a as f32 / 255.0;
+    let channel = |s: u8, shift: u32| {
+        let d = ((dst >> shift) & 0xff) as f32;
+        let s = s as f32;
+        let v = match mode {
+            BlendMode::Alpha => s * a + d * (1.0 - a),
+            BlendMode::Additive => d + s * a,
+            BlendMode::Multiply => d * (1.0 - a + a * s / 255.0),
+            BlendMode::Opaque => s,
+        };
+        (v.clamp(0.0, 255.0) as u32) << shift
+    };
+    0xff00_0000 | channel(src.r, 16) | channel(src.g, 8) | channel(src.b, 0)
+}
+
+/// Averages packed ARGB8888 values channel by channel
+fn average(values: &[u32]) -> u32 {
+    let mut sum = [0u32; 4];
+    for v in values {
+        for (c, total) in sum.iter_mut().enumerate() {
+            *total += (v >> (c * 8)) & 0xff;
+        }
+    }
+    let n = values.len() as u32;
+    (0..4).fold(0, |acc, c| acc | ((sum[c] + n / 2) / n) << (c * 8))
+}
+
+impl FrameBuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        FrameBuffer::with_anti_aliasing(width, height, AntiAliasing::None)
+    }
+
+    pub fn with_anti_aliasing(width: u32, height: u32, anti_aliasing: AntiAliasing) -> Self {
+        let anti_aliasing = match anti_aliasing {
+            AntiAliasing::Msaa(n) if sample_pattern(n as usize).len() != n as usize => AntiAliasing::None,
+            AntiAliasing::Supersample(f) if f < 2 => AntiAliasing::None,
+            aa => aa
+        };
+        let mut buffer = FrameBuffer {
+            width, height, pixels: Vec::new(), depth: Vec::new(), anti_aliasing, resolved: Vec::new()
+        };
+        let size = (buffer.target_width() * buffer.target_height()) as usize * buffer.samples();
+        buffer.pixels = vec![0; size];
+        buffer.depth = vec![f32::INFINITY; size];
+        if anti_aliasing != AntiAliasing::None {
+            buffer.resolved = vec![0; (width * height) as usize];
+        }
+        buffer
+    }
+
+    /// Width of the render target in pixels
+    pub fn target_width(&self) -> u32 {
+        match self.anti_aliasing {
+            AntiAliasing::Supersample(f) => self.width * f,
+            _ => self.width
+        }
+    }
+
+    /// Height of the render target in pixels
+    pub fn target_height(&self) -> u32 {
+        match self.anti_aliasing {
+            AntiAliasing::Supersample(f) => self.height * f,
+            _ => self.height
+        }
+    }
+
+    /// Amount of coverage samples stored per target pixel
+    pub fn samples(&self) -> usize {
+        match self.anti_aliasing {
+            AntiAliasing::Msaa(n) => n as usize,
+            _ => 1
+        }
+    }
+
+    /// Fills the target with the colour and resets the depth
+    pub fn clear(&mut self, color: Color) {
+        let packed = pack_color(color);
+        for p in self.pixels.iter_mut() {
+            *p = packed;
+        }
+        for d in self.depth.iter_mut() {
+            *d = f32::INFINITY;
+        }
+    }
+
+    /// The whole render target as a drawing region
+    pub fn region(&mut self) -> Region<'_> {
+        let (width, height) = (self.target_width() as i64, self.target_height() as i64);
+        let samples = self.samples();
+        Region {
+            pixels: &mut self.pixels,
+            depth: &mut self.depth,
+            width,
+            first_row: 0,
+            clip: [0, 0, width - 1, height - 1],
+            samples
+        }
+    }
+
+    /// Averages the samples (MSAA) or the blocks of pixels (supersampling) into the output image
+    pub fn resolve(&mut self) {
+        match self.anti_aliasing {
+            AntiAliasing::None => (),
+            AntiAliasing::Msaa(n) => {
+                for (out, samples) in self.resolved.iter_mut().zip(self.pixels.chunks(n as usize)) {
+                    *out = average(samples);
+                }
+            },
+            AntiAliasing::Supersample(f) => {
+                let (f, target_w) = (f as usize, self.target_width() as usize);
+                let mut block = Vec::with_capacity(f * f);
+                for y in 0..self.height as usize {
+                    for x in 0..self.width as usize {
+                        block.clear();
+                        for sy in 0..f {
+                            let row = (y * f + sy) * target_w + x * f;
+                            block.extend_from_slice(&self.pixels[row..row + f]);
+                        }
+                        self.resolved[y * self.width as usize + x] = average(&block);
+                    }
+                }
+            },
+        }
+    }
+
+    /// The final `width` x `height` image, valid after `resolve`
+    pub fn output(&self) -> &[u32] {
+        match self.anti_aliasing {
+            AntiAliasing::None => &self.pixels,
+            _ => &self.resolved
+        }
+    }
+}
+
+/// Returns the inclusive pixel bounding box of a screen-space triangle: (min_x, min_y, max_x, max_y)
+pub fn pixel_bounds(tri: &Triangle) -> [i64; 4] {
+    let pts = [snap(tri.p[0].x, tri.p[0].y), snap(tri.p[1].x, tri.p[1].y), snap(tri.p[2].x, tri.p[2].y)];
+    [pts.iter().map(|p| p.0).min().unwrap() >> SUBPIXEL_BITS,
+     pts.iter().map(|p| p.1).min().unwrap() >> SUBPIXEL_BITS,
+     pts.iter().map(|p| p.0).max().unwrap() >> SUBPIXEL_BITS,
+     pts.iter().map(|p| p.1).max().unwrap() >> SUBPIXEL_BITS]
+}
+
+/// A rectangular part of a render target the drawing is limited to.
+/// `pixels` and `depth` hold rows of `width` pixels with `samples` values each, starting at the screen row
+/// `first_row`, and only the pixels inside `clip` (min_x, min_y, max_x, max_y; inclusive) are touched.
+pub struct Region<'a> {
+    pub pixels: &'a mut [u32],
+    pub depth: &'a mut [f32],
+    pub width: i64,
+    pub first_row: i64,
+    pub clip: [i64; 4],
+    pub samples: usize
+}
+
+impl<'a> Region<'a> {
+    /// Half-space (edge function) rasterization of a screen-space triangle with a depth test.
+    /// Vertices are snapped to a fixed-point sub-pixel grid and the top-left fill rule decides
+    /// the ownership of pixels lying exactly on an edge, so triangles sharing an edge never
+    /// leave cracks or draw a pixel twice.
+    /// Varyings are interpolated perspective-correctly (`p.w` holds 1/w) and handed to `shader`,
+    /// which returns the colour of each pixel.
+    /// The edge functions are evaluated in whole-screen coordinates, so a triangle split
+    /// over several regions produces exactly the same pixels as when it's drawn at once.
+    ///
+    /// With several samples the coverage is tested at each sample position, the shader runs once
+    /// per pixel and its colour is stored in the covered samples only.
+    pub fn fill_triangle<F>(&mut self, tri: &Triangle, shader: F) where F: FnMut(&Varyings) -> Color {
+        self.fill_triangle_blended(tri, BlendMode::Opaque, shader);
+    }
+
+    /// Same as `fill_triangle`, but the shaded colour gets blended with the target.
+    /// Every sample is depth tested, only opaque triangles write their depth.
+    pub fn fill_triangle_blended<F>(&mut self, tri: &Triangle, blend: BlendMode, mut shader: F)
+        where F: FnMut(&Varyings) -> Color {
+        if tri.p.iter().any(|p| !p.x.is_finite() || !p.y.is_finite()) {
+            return
+        }
+        let mut v = [0, 1, 2];
+        let mut pts = [snap(tri.p[0].x, tri.p[0].y), snap(tri.p[1].x, tri.p[1].y), snap(tri.p[2].x, tri.p[2].y)];
+
+        let mut area = edge(pts[0], pts[1], pts[2]);
+        if area == 0 {
+            return
+        }
+        if area < 0 {
+            // keep a single orientation, so "inside" is always a positive edge function
+            pts.swap(1, 2);
+            v.swap(1, 2);
+            area = -area;
+        }
+
+        let min_x = (pts.iter().map(|p| p.0).min().unwrap() >> SUBPIXEL_BITS).max(self.clip[0]);
+        let min_y = (pts.iter().map(|p| p.1).min().unwrap() >> SUBPIXEL_BITS).max(self.clip[1]);
+        let max_x = (pts.iter().map(|p| p.0).max().unwrap() >> SUBPIXEL_BITS).min(self.clip[2]);
+        let max_y = (pts.iter().map(|p| p.1).max().unwrap() >> SUBPIXEL_BITS).min(self.clip[3]);
+        if min_x > max_x || min_y > max_y {
+            return
+        }
+
+        // edges are opposite to the vertex they weight: w0 = v1->v2, w1 = v2->v0, w2 = v0->v1
+        let edges = [(pts[1], pts[2]), (pts[2], pts[0]), (pts[0], pts[1])];
+        let bias = edges.map(|(a, b)| if is_top_left(a, b) { 0 } else { -1 });
+        let step_x = edges.map(|(a, b)| (a.1 - b.1) * SUBPIXEL_STEP);
+        let step_y = edges.map(|(a, b)| (b.0 - a.0) * SUBPIXEL_STEP);
+
+        // the edge functions are tracked at the pixel corner and offset to the sample positions
+        let pattern = sample_pattern(self.samples);
+        let ex = edges.map(|(a, b)| a.1 - b.1);
+        let ey = edges.map(|(a, b)| b.0 - a.0);
+        let center = SUBPIXEL_STEP / 2;
+        let origin = (min_x << SUBPIXEL_BITS, min_y << SUBPIXEL_BITS);
+        let mut row = edges.map(|(a, b)| edge(a, b, origin));
+
+        let inv_w = [tri.p[v[0]].w, tri.p[v[1]].w, tri.p[v[2]].w];
+        let z = [tri.p[v[0]].z, tri.p[v[1]].z, tri.p[v[2]].z];
+        let write_depth = blend == BlendMode::Opaque;
+        let vars = [tri.var[v[0]], tri.var[v[1]], tri.var[v[2]]];
+        let inv_area = 1.0 / area as f32;
+
+        for y in min_y..=max_y {
+            let mut w = row;
+            for x in min_x..=max_x {
+                let start = (((y - self.first_row) * self.width + x) as usize) * self.samples;
+                let mut mask = 0u32;
+                let mut depths = [0.0; 8];
+                for (s, &(ox, oy)) in pattern.iter().enumerate() {
+                    let ws = [0, 1, 2].map(|i| w[i] + ex[i] * ox + ey[i] * oy);
+                    if (0..3).all(|i| ws[i] + bias[i] >= 0) {
+                        // NDC depth is affine in screen space, so plain barycentrics are enough
+                        depths[s] = (ws[0] as f32 * z[0] + ws[1] as f32 * z[1] + ws[2] as f32 * z[2]) * inv_area;
+                        if depths[s] < self.depth[start + s] {
+                            mask |= 1 << s;
+                        }
+                    }
+                }
+                if mask != 0 {
+                    let wc = [0, 1, 2].map(|i| w[i] + ex[i] * center + ey[i] * center);
+                    let b = [wc[0] as f32 * inv_area * inv_w[0],
+                             wc[1] as f32 * inv_area * inv_w[1],
+                             wc[2] as f32 * inv_area * inv_w[2]];
+                    let one_over_w = b[0] + b[1] + b[2];
+                    let var = Varyings::weighted(&vars, [b[0] / one_over_w, b[1] / one_over_w, b[2] / one_over_w]);
+                    let color = shader(&var);
+                    for (s, &depth) in depths.iter().enumerate().take(self.samples) {
+                        if mask & (1 << s) != 0 {
+                            self.pixels[start + s] = blend_color(blend, color, self.pixels[start + s]);
+                            if write_depth {
+                                self.depth[start + s] = depth;
+                            }
+                        }
+                    }
+                }
+                for i in 0..3 {
+                    w[i] += step_x[i];
+                }
+            }
+            for i in 0..3 {
+                row[i] += step_y[i];
+            }
+        }
+    }
+
+    /// Sets every sample of a pixel, if it lies inside the region
+    pub fn put(&mut self, x: i64, y: i64, color: u32) {
+        if x < self.clip[0] || y < self.clip[1] || x > self.clip[2] || y > self.clip[3] {
+            return
+        }
+        let start = (((y - self.first_row) * self.width + x) as usize) * self.samples;
+        for p in &mut self.pixels[start..start + self.samples] {
+            *p = color;
+        }
+    }
+
+    /// Draws a square dot of `size` pixels centered at the point
+    pub fn draw_point(&mut self, x: f32, y: f32, color: Color, size: u32) {
+        if !x.is_finite() || !y.is_finite() {
+            return
+        }
+        let packed = pack_color(color);
+        let half = (size as f32 - 1.0) * 0.5;
+        let (x0, y0) = ((x - half).floor() as i64, (y - half).floor() as i64);
+        for dy in 0..size.max(1) as i64 {
+            for dx in 0..size.max(1) as i64 {
+                self.put(x0 + dx, y0 + dy, packed);
+            }
+        }
+    }
+
+    /// DDA line of the given thickness between two screen-space points
+    pub fn draw_line(&mut self, from: (f32, f32), to: (f32, f32), color: Color, thickness: u32) {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let steps = dx.abs().max(dy.abs()).ceil();
+        if !steps.is_finite() {
+            return
+        }
+        let steps = steps as i64;
+        for i in 0..=steps {
+            let t = if steps > 0 { i as f32 / steps as f32 } else { 0.0 };
+            self.draw_point(from.0 + dx * t, from.1 + dy * t, color, thickness);
+        }
+    }
+
+    /// Draws a screen-space triangle the way the render mode requires
+    pub fn draw_triangle<F>(&mut self, tri: &Triangle, mode: RenderMode, blend: BlendMode, style: &WireStyle, shader: F)
+        where F: FnMut(&Varyings) -> Color {
+        let points = [(tri.p[0].x, tri.p[0].y), (tri.p[1].x, tri.p[1].y), (tri.p[2].x, tri.p[2].y)];
+        match mode {
+            RenderMode::Solid | RenderMode::SolidWireframe => self.fill_triangle_blended(tri, blend, shader),
+            RenderMode::HiddenLine => self.fill_triangle(tri, |_| style.background),
+            RenderMode::Wireframe | RenderMode::Points => (),
+        }
+        match mode {
+            RenderMode::SolidWireframe | RenderMode::Wireframe | RenderMode::HiddenLine => {
+                for i in 0..3 {
+                    self.draw_line(points[i], points[(i + 1) % 3], style.color, style.thickness);
+                }
+            },
+            RenderMode::Points => {
+                for p in points.iter() {
+                    self.draw_point(p.0, p.1, style.color, style.thickness + 2);
+                }
+            },
+            RenderMode::Solid => (),
+        }
+    }
+}
+
+/// Amount of fractional bits of the fixed-point vertex coordinates
+const SUBPIXEL_BITS: i64 = 4;
+const SUBPIXEL_STEP: i64 = 1 << SUBPIXEL_BITS;
+
+/// Snaps a screen-space point to the sub-pixel grid
+fn snap(x: f32, y: f32) -> (i64, i64) {
+    ((x * SUBPIXEL_STEP as f32).round() as i64, (y * SUBPIXEL_STEP as f32).round() as i64)
+}
+
+/// Edge function: doubled signed area of the (a, b, p) triangle
+fn edge(a: (i64, i64), b: (i64, i64), p: (i64, i64)) -> i64 {
+    (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0)
+}
+
+/// Top-left fill rule for a positively oriented triangle in y-down screen space:
+/// a top edge is horizontal and goes right, a left edge goes up
+fn is_top_left(a: (i64, i64), b: (i64, i64)) -> bool {
+    (a.1 == b.1 && b.0 > a.0) || b.1 < a.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::naive::gfx::Vec3D;
+
+    /// Draws the triangles additively, one step of red each, and returns how many times
+    /// every pixel of the 48x48 buffer was written
+    fn coverage(triangles: &[[(f32, f32); 3]]) -> Vec<u32> {
+        let mut buffer = FrameBuffer::new(48, 48);
+        buffer.clear(Color::RGBA(0, 0, 0, 255));
+        for p in triangles {
+            let [a, b, c] = p.map(|(x, y)| Vec3D::new(x, y, 0.5));
+            let tri = Triangle::new(a, b, c, None);
+            buffer.region().fill_triangle_blended(&tri, BlendMode::Additive, |_| Color::RGBA(1, 0, 0, 255));
+        }
+        buffer.output().iter().map(|p| (p >> 16) & 0xff).collect()
+    }
+
+    /// Two triangles on both sides of an edge running through pixel centres, from `a` to `b`:
+    /// nothing is written twice and every pixel centre inside the edge is written
+    fn assert_watertight(a: (f32, f32), b: (f32, f32), left: (f32, f32), right: (f32, f32)) {
+        let counts = coverage(&[[a, b, left], [b, a, right]]);
+        assert!(counts.iter().all(|&n| n <= 1), "a pixel was written twice");
+        let steps = (b.0 - a.0).abs().max((b.1 - a.1).abs()) as i32;
+        for i in 1..steps {
+            let t = i as f32 / steps as f32;
+            let (x, y) = (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+            assert_eq!(counts[y as usize * 48 + x as usize], 1, "crack at ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn shared_horizontal_edge_is_watertight() {
+        assert_watertight((5.5, 20.5), (40.5, 20.5), (22.0, 3.0), (18.0, 39.0));
+    }
+
+    #[test]
+    fn shared_vertical_edge_is_watertight() {
+        assert_watertight((20.5, 5.5), (20.5, 40.5), (3.0, 25.0), (38.0, 17.0));
+    }
+
+    #[test]
+    fn shared_diagonal_edge_is_watertight() {
+        assert_watertight((5.5, 5.5), (40.5, 40.5), (40.0, 4.0), (4.0, 42.0));
+    }
+}
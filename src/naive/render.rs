@@ -1,19 +1,28 @@
 extern crate sdl2;
 
 use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 
 use sdl2::{
     event::Event,
     image::{InitFlag, LoadTexture},
     keyboard::Keycode,
-    pixels::Color,
+    pixels::{Color, PixelFormatEnum},
     rect::Rect,
     render::TextureQuery,
 };
 
 use super::text::Text;
-use self::sdl2::gfx::primitives::DrawRenderer;
+use super::raster::{AntiAliasing, FrameBuffer, Region, RenderMode, WireStyle};
+use super::tiles::TileRenderer;
+use super::shadow::{self, LightProjection, ShadowMap};
+use super::gfx::{clip, culling, Camera, Light, Matrix, Mesh, ShadingMode, Triangle, Varyings, Vec3D};
+use super::gfx::shading::{Lighting, Material};
+use super::gfx::varying::{VAR_FOG, VAR_WORLD};
+use super::gfx::fog::Fog;
+use super::gfx::frustum::Frustum;
+use super::gfx::shader::{self, FragmentShader, VertexShader};
 
 // handle the annoying Rect i32
 macro_rules! rect(
@@ -74,43 +83,26 @@ impl Window {
         }
     }
 
-    pub fn set_color(&mut self, clr: Color) {
-        self.canvas.set_draw_color(clr);
-    }
-
     pub fn set_title(&mut self, title: &str) {
         let window = self.canvas.window_mut();
 
         window.set_title(&title).map_err(|e| e.to_string()).unwrap();
     }
 
-    pub fn draw_line(&mut self, color: Color, start: (i32,i32), finish: (i32,i32)) -> Result<(), String> {
-        self.canvas.set_draw_color(color);
-        self.canvas.draw_line(start, finish)?;
-        Ok(())
-    }
-
-    pub fn draw_triangle(&mut self, color: Color, point1: (i32,i32),
-                         point2: (i32,i32), point3: (i32,i32)) -> Result<(), String> {
-        self.canvas.set_draw_color(color);
-        self.canvas.draw_line(point1, point2)?;
-        self.canvas.draw_line(point2, point3)?;
-        self.canvas.draw_line(point3, point1)?;
-        Ok(())
-    }
-
-    pub fn draw_triangle_fast(&mut self, color: Color, point1: (i16,i16),
-                         point2: (i16,i16), point3: (i16,i16)) -> Result<(), String> {
-        self.canvas.trigon(point1.0,point1.1,point2.0,point2.1,point3.0,point3.1,color)?;
-        Ok(())
-    }
-
-    // Uses SDL_GFX primitives to draw filled triangles
-    pub fn fill_triangle(&mut self, color: Color, point1: (i32,i32),
-                         point2: (i32,i32), point3: (i32,i32)) -> Result<(), String> {
-        self.canvas.filled_trigon(point1.0 as i16, point1.1 as i16,
-                                  point2.0 as i16, point2.1 as i16,
-                                  point3.0 as i16, point3.1 as i16, color)?;
+    /// Copies a resolved software frame buffer onto the canvas, stretching it over the whole window
+    pub fn draw_buffer(&mut self, buffer: &FrameBuffer) -> Result<(), String> {
+        let texture_c = self.canvas.texture_creator();
+        let mut texture = texture_c.create_texture_streaming(PixelFormatEnum::ARGB8888, buffer.width, buffer.height)
+            .map_err(|e| e.to_string())?;
+        texture.with_lock(None, |bytes: &mut [u8], pitch: usize| {
+            for (y, row) in buffer.output().chunks(buffer.width as usize).enumerate() {
+                let line = &mut bytes[y * pitch..y * pitch + row.len() * 4];
+                for (px, dst) in row.iter().zip(line.chunks_mut(4)) {
+                    dst.copy_from_slice(&px.to_ne_bytes());
+                }
+            }
+        })?;
+        self.canvas.copy(&texture, None, None)?;
         Ok(())
     }
 
@@ -141,6 +133,11 @@ impl Window {
         &self.ctx
     }
 
+    /// Hides the cursor and reports only relative mouse motion, which is what mouse-look needs
+    pub fn set_relative_mouse_mode(&self, on: bool) {
+        self.ctx.mouse().set_relative_mouse_mode(on);
+    }
+
     pub fn create_event_pump(&self) -> sdl2::EventPump{
         self.ctx.event_pump().unwrap()
     }
@@ -161,3 +158,355 @@ impl Window {
     }
 }
 
+
+/// The software rendering pipeline.
+/// A frame is `begin_frame`, any number of `draw` calls and `end_frame`: draw calls transform,
+/// cull, light and clip the meshes into a queue of screen space triangles, `end_frame` sorts the
+/// queue and rasterizes it into `buffer`, which is then ready to be shown by `Window::draw_buffer`.
+/// With a `shadow` map the shadow casters go through `cast_shadow` before the draw calls.
+pub struct Renderer {
+    pub buffer: FrameBuffer,
+    pub tiles: TileRenderer,
+    /// Splits the work over the tile renderer threads
+    pub tiled: bool,
+    pub shading: ShadingMode,
+    pub render_mode: RenderMode,
+    pub wire_style: WireStyle,
+    /// Lights of the built-in shading, summed
+    pub lights: Vec<Light>,
+    pub background: Color,
+    pub shadow: Option<ShadowMap>,
+    /// Applied to the built-in shading only, custom fragment shaders are left as they are
+    pub fog: Option<Fog>,
+    /// Skips the objects outside of the camera frustum before any of their triangles gets processed
+    pub frustum_culling: bool,
+    queue: Vec<Triangle>,
+    /// Materials of the queued triangles, `Triangle::material` indexes them during a frame.
+    /// Triangles of `draw_with` calls have their fragment shader next to the material.
+    materials: Vec<(Material, Option<Arc<dyn FragmentShader>>)>,
+    /// Viewer position of the last draw call, per pixel lighting needs it
+    eye: Vec3D,
+    /// Objects skipped by the frustum culling in the current frame
+    culled: usize,
+    /// Screen space triangles outlined over the frame, see `highlight`
+    overlay: Vec<Triangle>
+}
+
+impl Renderer {
+    pub fn new(width: u32, height: u32, anti_aliasing: AntiAliasing) -> Self {
+        let background = Color::RGBA(0, 0, 0, 255);
+        let white = Color::RGBA(255, 255, 255, 255);
+        let mut sun = Light::directional(Vec3D::new(0.0, 1.0, -1.0), white, 1.0);
+        sun.casts_shadow = true;
+        Renderer {
+            buffer: FrameBuffer::with_anti_aliasing(width, height, anti_aliasing),
+            tiles: TileRenderer::new(64),
+            tiled: true,
+            shading: ShadingMode::Flat,
+            render_mode: RenderMode::Solid,
+            wire_style: WireStyle { color: Color::RGB(0, 200, 255), thickness: 1, background },
+            lights: vec![Light::ambient(white, 1.0), sun],
+            background,
+            shadow: None,
+            fog: None,
+            frustum_culling: true,
+            queue: Vec::new(),
+            materials: Vec::new(),
+            eye: Vec3D::init(),
+            culled: 0,
+            overlay: Vec::new()
+        }
+    }
+
+    /// Sets the colour the frame gets cleared with, the hidden-line fill and the fog follow it
+    pub fn set_background(&mut self, color: Color) {
+        self.background = color;
+        self.wire_style.background = color;
+        if let Some(fog) = self.fog.as_mut() {
+            fog.color = color;
+        }
+    }
+
+    /// Clears the buffer and the queue of the previous frame
+    pub fn begin_frame(&mut self) {
+        self.buffer.clear(self.background);
+        self.wire_style.background = self.background;
+        self.queue.clear();
+        self.materials.clear();
+        self.culled = 0;
+        self.overlay.clear();
+        if let Some(shadow) = self.shadow.as_mut() {
+            shadow.begin();
+        }
+    }
+
+    /// Creates a `size` shadow map for the light casting shadows, see `shadow::shadow_caster`.
+    /// Without such a light the renderer is left without shadows and false is returned.
+    pub fn enable_shadows(&mut self, size: u32, pcf: u32) -> bool {
+        let caster = shadow::shadow_caster(&self.lights);
+        let light = caster.and_then(|i| LightProjection::for_light(&self.lights[i], Vec3D::init(), 1.0));
+        self.shadow = caster.zip(light).map(|(caster, light)| {
+            let mut shadow = ShadowMap::new(light, size);
+            shadow.caster = caster;
+            shadow.pcf = pcf;
+            shadow
+        });
+        self.shadow.is_some()
+    }
+
+    /// Points the shadow map at its light, to be done before `begin_frame` when the light or the
+    /// scene moved. The sphere (center, radius) should enclose the casters, see `Scene::bounding_sphere`.
+    pub fn aim_shadow(&mut self, center: Vec3D, radius: f32) {
+        let lights = &self.lights;
+        if let Some(shadow) = self.shadow.as_mut() {
+            if let Some(light) = lights.get(shadow.caster).and_then(|l| LightProjection::for_light(l, center, radius)) {
+                shadow.light = light;
+            }
+        }
+    }
+
+    /// Renders a mesh placed by `transform` into the shadow map, if there's one
+    pub fn cast_shadow(&mut self, mesh: &Mesh, transform: &Matrix) {
+        if let Some(shadow) = self.shadow.as_mut() {
+            shadow.cast(mesh, transform);
+        }
+    }
+
+    /// Number of screen space triangles queued in the current frame
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Number of objects skipped by the frustum culling in the current frame
+    pub fn culled(&self) -> usize {
+        self.culled
+    }
+
+    /// Tests the bounds of a mesh placed by `transform` against the camera frustum: first the
+    /// bounding sphere, then the box. Returns true (and counts it) when the object can't be seen.
+    /// `draw` does it on its own, `draw_with` callers can use it to skip the call.
+    pub fn cull_object(&mut self, mesh: &Mesh, transform: &Matrix, camera: &Camera) -> bool {
+        if !self.frustum_culling {
+            return false
+        }
+        let frustum = Frustum::from_matrix(&camera.view_projection());
+        let bounds = mesh.bounds.transformed(transform);
+        let (center, radius) = bounds.bounding_sphere();
+        let visible = frustum.intersects_sphere(&center, radius) && frustum.intersects_aabb(&bounds);
+        if !visible {
+            self.culled += 1;
+        }
+        !visible
+    }
+
+    /// Outlines a world space triangle over everything else, e.g. a picked one
+    pub fn highlight(&mut self, triangle: &[Vec3D; 3], camera: &Camera, color: Color) {
+        let (width, height) = self.target_size();
+        let view_projection = camera.view_projection();
+        let p = triangle.map(|p| view_projection.apply(&p));
+        for mut projection in clip::clip_triangle(&Triangle::new(p[0], p[1], p[2], Some(color))) {
+            projection.normalize();
+            projection.map_to_viewport(width, height);
+            self.overlay.push(projection);
+        }
+    }
+
+    /// Queues a mesh placed in the world by `transform`, seen from the camera and lit with the built-in shading
+    pub fn draw(&mut self, mesh: &Mesh, transform: &Matrix, camera: &Camera) {
+        self.draw_material(mesh, transform, camera, None);
+    }
+
+    /// Same as `draw`, but a given material replaces the ones of the mesh
+    pub fn draw_material(&mut self, mesh: &Mesh, transform: &Matrix, camera: &Camera, material: Option<&Material>) {
+        if self.cull_object(mesh, transform, camera) {
+            return
+        }
+        let offset = self.push_materials(mesh, material, None);
+        let replaced = material;
+        let (width, height) = self.target_size();
+        let (shading, eye, fog) = (self.shading, camera.position, self.fog);
+        self.eye = eye;
+        let lighting = Lighting {
+            lights: &self.lights, eye, blinn: false, shadow_caster: self.shadow.as_ref().map(|s| s.caster)
+        };
+        let shadow = self.shadow.as_ref();
+        let visibility = |p: &Vec3D| shadow.map_or(1.0, |s| s.visibility(p));
+
+        // Every shared vertex gets transformed only once per frame, in SIMD batches
+        let mvp = Matrix::model_view_projection(transform, &camera.view_matrix(), &camera.projection_matrix());
+        let world_positions = transform.apply_batch(&mesh.positions);
+        let mut world_normals = transform.normal_matrix().apply_batch(&mesh.normals);
+        world_normals.normalize();
+        let clip_positions = mvp.apply_batch(&mesh.positions);
+
+        let process = |i: usize, tri: &Triangle, out: &mut Vec<Triangle>| {
+            let mut face = mesh.faces[i];
+            let mut t_transformed = Triangle {
+                p: face.map(|v| world_positions.get(v)),
+                n: face.map(|v| world_normals.get(v)),
+                material: offset + if replaced.is_some() { 0 } else { tri.material },
+                ..*tri
+            };
+            let material = replaced.unwrap_or(&mesh.materials[tri.material]);
+
+            // a ray from camera to triangle
+            let camera_ray = camera.view_ray(&t_transformed.p[0]);
+            let front = culling::is_front_facing(&t_transformed.face_normal(), &camera_ray, material.winding);
+            if !material.cull.keeps(front) {
+                return
+            }
+            if !front {
+                // two-sided lighting: a visible back face is lit from its own side
+                t_transformed.flip();
+                face.swap(1, 2);
+            }
+            match shading {
+                ShadingMode::Flat => t_transformed.shade(material, &lighting, visibility),
+                ShadingMode::Gouraud => t_transformed.shade_vertices(material, &lighting, visibility),
+                _ => t_transformed.store_surface(),
+            }
+            // per pixel shading evaluates the fog per pixel too, the other modes per vertex
+            if let (Some(fog), ShadingMode::Flat) | (Some(fog), ShadingMode::Gouraud) = (fog, shading) {
+                for i in 0..3 {
+                    t_transformed.var[i].data[VAR_FOG] = fog.factor((t_transformed.p[i] - eye).len());
+                }
+            }
+
+            // 3D -> clip space with the combined matrix, clipped against the whole frustum
+            let t_projected = Triangle { p: face.map(|v| clip_positions.get(v)), ..t_transformed };
+            for mut projection in clip::clip_triangle(&t_projected) {
+                projection.normalize();
+                projection.map_to_viewport(width, height);
+                out.push(projection);
+            }
+        };
+        let triangles = self.process_faces(mesh, process);
+        self.queue.extend(triangles);
+    }
+
+    /// Queues a mesh drawn with custom shaders: `vertex` runs once for every shared vertex
+    /// and places it in clip space, `fragment` colours the pixels
+    pub fn draw_with(&mut self, mesh: &Mesh, vertex: &dyn VertexShader, fragment: Arc<dyn FragmentShader>) {
+        let offset = self.push_materials(mesh, None, Some(fragment));
+        let (width, height) = self.target_size();
+
+        let run_vertex = |i: usize, out: &mut Vec<(Vec3D, Varyings)>| {
+            out.push(shader::run_vertex_shader(vertex, mesh, i))
+        };
+        let count = mesh.positions.len();
+        let vertices = if self.tiled {
+            self.tiles.process_indices(count, run_vertex)
+        } else {
+            let mut out = Vec::with_capacity(count);
+            for i in 0..count {
+                run_vertex(i, &mut out);
+            }
+            out
+        };
+
+        let process = |i: usize, tri: &Triangle, out: &mut Vec<Triangle>| {
+            let face = mesh.faces[i];
+            let t_projected = Triangle {
+                p: face.map(|v| vertices[v].0),
+                var: face.map(|v| vertices[v].1),
+                material: offset + tri.material,
+                ..*tri
+            };
+            let material = &mesh.materials[tri.material];
+            let front = culling::is_front_facing_clip(&t_projected.p, material.winding);
+            if material.cull.keeps(front) {
+                for mut projection in clip::clip_triangle(&t_projected) {
+                    projection.normalize();
+                    projection.map_to_viewport(width, height);
+                    out.push(projection);
+                }
+            }
+        };
+        let triangles = self.process_faces(mesh, process);
+        self.queue.extend(triangles);
+    }
+
+    /// Sorts the queued triangles, rasterizes them and resolves the buffer
+    pub fn end_frame(&mut self) {
+        let Renderer { buffer, tiles, tiled, shading, render_mode, wire_style, lights, shadow, fog, queue, materials, eye, overlay, .. } = self;
+        let (shading, render_mode, eye, fog) = (*shading, *render_mode, *eye, *fog);
+        let lighting = Lighting {
+            lights, eye, blinn: shading == ShadingMode::BlinnPhong, shadow_caster: shadow.as_ref().map(|s| s.caster)
+        };
+
+        queue.sort_by(|t1, t2| {
+            let z1 = (t1.p[0].z + t1.p[1].z + t1.p[2].z) / 3.0;
+            let z2 = (t2.p[0].z + t2.p[1].z + t2.p[2].z) / 3.0;
+            z2.total_cmp(&z1)
+        });
+        // transparent triangles go after the opaque ones, still back to front (the sort is stable)
+        queue.sort_by_key(|t| materials[t.material].0.is_transparent());
+
+        let shade = |tri: &Triangle, v: &Varyings| match &materials[tri.material] {
+            (_, Some(fragment)) => fragment.fragment(v),
+            (material, None) => match shading {
+                ShadingMode::Flat | ShadingMode::Gouraud => {
+                    let color = if shading == ShadingMode::Flat { tri.color } else { v.color() };
+                    fog.map_or(color, |fog| fog.blend(color, v.data[VAR_FOG]))
+                },
+                ShadingMode::Phong | ShadingMode::BlinnPhong => {
+                    let world = v.vec(VAR_WORLD);
+                    let visibility = shadow.as_ref().map_or(1.0, |s| s.visibility(&world));
+                    let color = material.shade_fragment(v, &lighting, visibility);
+                    fog.map_or(color, |fog| fog.apply(color, (world - eye).len()))
+                },
+            },
+        };
+        let style = &*wire_style;
+        let draw = |region: &mut Region, tri: &Triangle| {
+            let blend = materials[tri.material].0.blend;
+            region.draw_triangle(tri, render_mode, blend, style, |v| shade(tri, v))
+        };
+        if *tiled {
+            tiles.rasterize(buffer, queue, style.thickness as i64 + 1, draw);
+        } else {
+            let mut region = buffer.region();
+            for tri in queue.iter() {
+                draw(&mut region, tri);
+            }
+        }
+        let mut region = buffer.region();
+        for tri in overlay.iter() {
+            for i in 0..3 {
+                let (a, b) = (tri.p[i], tri.p[(i + 1) % 3]);
+                region.draw_line((a.x, a.y), (b.x, b.y), tri.color, style.thickness + 1);
+            }
+        }
+        buffer.resolve();
+    }
+
+    fn target_size(&self) -> (f32, f32) {
+        (self.buffer.target_width() as f32, self.buffer.target_height() as f32)
+    }
+
+    /// Appends the materials of a mesh (or the one replacing them) to the frame,
+    /// returns the index of the first one
+    fn push_materials(&mut self, mesh: &Mesh, material: Option<&Material>,
+                      fragment: Option<Arc<dyn FragmentShader>>) -> usize {
+        let offset = self.materials.len();
+        match material {
+            Some(m) => self.materials.push((*m, fragment)),
+            None => self.materials.extend(mesh.materials.iter().map(|m| (*m, fragment.clone()))),
+        }
+        offset
+    }
+
+    /// Runs `process` over every face of the mesh, on the worker threads when tiled
+    fn process_faces<F>(&self, mesh: &Mesh, process: F) -> Vec<Triangle>
+        where F: Fn(usize, &Triangle, &mut Vec<Triangle>) + Sync {
+        if self.tiled {
+            self.tiles.process(&mesh.tris, process)
+        } else {
+            let mut out = Vec::new();
+            for (i, tri) in mesh.tris.iter().enumerate() {
+                process(i, tri, &mut out);
+            }
+            out
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use super::gfx::{Camera, Matrix, Mesh, Quaternion, Vec3D};
+use super::gfx::shading::Material;
+use super::gfx::vector;
+use super::gfx::bounds::Aabb;
+use super::gfx::bvh::{Bvh, MeshBvh, NearestPoint, Ray, RayHit};
+use super::render::Renderer;
+
+pub mod file;
+
+/// Local placement of a node relative to its parent: scaled, then rotated, then translated
+#[derive(Copy, Clone, Debug)]
+pub struct Transform {
+    pub translation: Vec3D,
+    pub rotation: Quaternion,
+    pub scale: Vec3D
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Transform {
+            translation: Vec3D::init(),
+            rotation: Quaternion::identity(),
+            scale: Vec3D::new(1.0, 1.0, 1.0)
+        }
+    }
+
+    pub fn from_translation(x: f32, y: f32, z: f32) -> Self {
+        Transform { translation: Vec3D::new(x, y, z), ..Transform::identity() }
+    }
+
+    pub fn to_matrix(self) -> Matrix {
+        let t = &self.translation;
+        Matrix::init_scale(self.scale.x, self.scale.y, self.scale.z)
+            * self.rotation.to_matrix()
+            * Matrix::init_translation(t.x, t.y, t.z)
+    }
+}
+
+/// An element of the scene graph. Meshes are shared, a node refers to one of `Scene::meshes`
+/// and can replace its materials with its own.
+pub struct Node {
+    pub name: String,
+    pub mesh: Option<usize>,
+    pub material: Option<Material>,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    transform: Transform,
+    world: Matrix,
+    dirty: bool
+}
+
+impl Node {
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+}
+
+/// A hierarchy of nodes, each placed relative to its parent.
+/// Nodes are kept in a flat list and referred to by their index.
+/// World matrices are cached: changing a local transform marks the node, and `update`
+/// recomputes the marked nodes together with everything below them.
+pub struct Scene {
+    pub nodes: Vec<Node>,
+    pub meshes: Vec<Mesh>,
+    pub roots: Vec<usize>
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Scene { nodes: Vec::new(), meshes: Vec::new(), roots: Vec::new() }
+    }
+
+    /// Stores a mesh and returns its index, nodes refer to meshes by it
+    pub fn add_mesh(&mut self, mesh: Mesh) -> usize {
+        self.meshes.push(mesh);
+        self.meshes.len() - 1
+    }
+
+    /// Adds a node under `parent` (or at the top level) and returns its index
+    pub fn add_node(&mut self, name: &str, parent: Option<usize>, transform: Transform, mesh: Option<usize>) -> usize {
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            name: name.to_string(),
+            mesh,
+            material: None,
+            parent,
+            children: Vec::new(),
+            transform,
+            world: Matrix::init_identity(),
+            dirty: true
+        });
+        match parent {
+            Some(p) => self.nodes[p].children.push(id),
+            None => self.roots.push(id),
+        }
+        id
+    }
+
+    /// Gives access to the local transform of a node, marking it for `update`
+    pub fn transform_mut(&mut self, node: usize) -> &mut Transform {
+        self.nodes[node].dirty = true;
+        &mut self.nodes[node].transform
+    }
+
+    /// Recomputes the world matrices of the changed nodes and their descendants
+    pub fn update(&mut self) {
+        for i in 0..self.roots.len() {
+            let root = self.roots[i];
+            self.update_node(root, &Matrix::init_identity(), false);
+        }
+    }
+
+    fn update_node(&mut self, id: usize, parent_world: &Matrix, parent_changed: bool) {
+        let changed = parent_changed || self.nodes[id].dirty;
+        if changed {
+            let node = &mut self.nodes[id];
+            node.world = node.transform.to_matrix() * *parent_world;
+            node.dirty = false;
+        }
+        let world = self.nodes[id].world;
+        for i in 0..self.nodes[id].children.len() {
+            let child = self.nodes[id].children[i];
+            self.update_node(child, &world, changed);
+        }
+    }
+
+    /// Nodes with a mesh: (node index, mesh, world matrix)
+    pub fn drawables(&self) -> impl Iterator<Item = (usize, &Mesh, &Matrix)> {
+        self.nodes.iter().enumerate()
+            .filter_map(move |(i, n)| n.mesh.map(|m| (i, &self.meshes[m], &n.world)))
+    }
+
+    /// Returns a sphere (center, radius) enclosing every mesh of the scene
+    pub fn bounding_sphere(&self) -> (Vec3D, f32) {
+        let spheres: Vec<(Vec3D, f32)> = self.drawables()
+            .map(|(_, mesh, world)| mesh.bounding_sphere(world))
+            .collect();
+        if spheres.is_empty() {
+            return (Vec3D::init(), 0.0)
+        }
+        let mut center = Vec3D::init();
+        for (c, _) in &spheres {
+            center += *c;
+        }
+        center = vector::vec_div_by(&center, spheres.len() as f32);
+        let radius = spheres.iter().map(|(c, r)| (*c - center).len() + r).fold(0.0, f32::max);
+        (center, radius)
+    }
+
+    /// Vertices of a triangle of a node's mesh, placed in the world
+    pub fn world_triangle(&self, node: usize, triangle: usize) -> Option<[Vec3D; 3]> {
+        let node = &self.nodes[node];
+        let mesh = &self.meshes[node.mesh?];
+        let face = mesh.faces.get(triangle)?;
+        Some(face.map(|v| node.world.apply(&mesh.positions.get(v))))
+    }
+
+    /// Renders every mesh into the shadow map of the renderer
+    pub fn cast_shadows(&self, renderer: &mut Renderer) {
+        for (_, mesh, world) in self.drawables() {
+            renderer.cast_shadow(mesh, world);
+        }
+    }
+
+    /// Queues every mesh of the scene with the built-in shading
+    pub fn draw(&self, renderer: &mut Renderer, camera: &Camera) {
+        for (i, mesh, world) in self.drawables() {
+            match &self.nodes[i].material {
+                Some(material) => renderer.draw_material(mesh, world, camera, Some(material)),
+                None => renderer.draw(mesh, world, camera),
+            }
+        }
+    }
+}
+
+/// What's under a point of the screen, see `SceneBvh::pick`
+#[derive(Copy, Clone, Debug)]
+pub struct Pick {
+    pub node: usize,
+    /// Index into the `Mesh::tris` of the node's mesh
+    pub triangle: usize,
+    /// World space hit point
+    pub point: Vec3D,
+    /// World space normal of the triangle, facing the camera
+    pub normal: Vec3D,
+    /// Distance along the pick ray, from the near plane
+    pub distance: f32
+}
+
+/// A mesh node as the scene BVH sees it
+struct Instance {
+    node: usize,
+    mesh: usize,
+    world: Matrix,
+    inverse: Matrix
+}
+
+/// Spatial queries over a whole scene: a top level BVH over the world boxes of the mesh nodes
+/// and one `MeshBvh` per mesh, shared by the nodes using it. Queries against an object run in
+/// its model space, so moving nodes only needs the cheap `update` of the top level.
+pub struct SceneBvh {
+    pub meshes: Vec<MeshBvh>,
+    objects: Bvh,
+    instances: Vec<Instance>
+}
+
+impl SceneBvh {
+    pub fn new(scene: &Scene) -> Self {
+        let mut bvh = SceneBvh {
+            meshes: scene.meshes.iter().map(MeshBvh::new).collect(),
+            objects: Bvh::build(&[]),
+            instances: Vec::new()
+        };
+        bvh.update(scene);
+        bvh
+    }
+
+    /// Rebuilds the top level after nodes were moved, expects `Scene::update` to be done.
+    /// Meshes added to the scene since `new` are not picked up.
+    pub fn update(&mut self, scene: &Scene) {
+        self.instances = scene.nodes.iter().enumerate()
+            .filter_map(|(node, n)| {
+                let mesh = n.mesh.filter(|&m| m < self.meshes.len())?;
+                let inverse = n.world.inverse()?;
+                Some(Instance { node, mesh, world: n.world, inverse })
+            })
+            .collect();
+        let bounds: Vec<Aabb> = self.instances.iter()
+            .map(|i| self.meshes[i.mesh].bounds().transformed(&i.world))
+            .collect();
+        self.objects = Bvh::build(&bounds);
+    }
+
+    /// First triangle along a world space ray: (node, hit), the hit point is in the world
+    pub fn intersect_ray(&self, ray: &Ray, max_t: f32) -> Option<(usize, RayHit)> {
+        self.objects.ray_cast(ray, max_t, |i, limit| {
+            let instance = &self.instances[i];
+            let local = ray.transformed(&instance.inverse);
+            self.meshes[instance.mesh].intersect_ray(&local, limit)
+                .map(|hit| (hit.t, (instance.node, RayHit { point: ray.at(hit.t), ..hit })))
+        }).map(|(_, hit)| hit)
+    }
+
+    /// Finds the triangle under a point of the screen (pixels of a `width` x `height` target)
+    pub fn pick(&self, camera: &Camera, x: f32, y: f32, width: f32, height: f32) -> Option<Pick> {
+        let ray = camera.screen_ray(x, y, width, height)?;
+        let (node, hit) = self.intersect_ray(&ray, camera.far)?;
+        let instance = self.instances.iter().find(|i| i.node == node)?;
+        let p = self.meshes[instance.mesh].triangle(hit.triangle).map(|p| instance.world.apply(&p));
+        let mut normal = vector::cross_product(&(p[1] - p[0]), &(p[2] - p[0]));
+        normal.normalize();
+        if normal.dot_product(&ray.direction) > 0.0 {
+            normal = vector::vec_mul_by(&normal, -1.0);
+        }
+        Some(Pick { node, triangle: hit.triangle, point: hit.point, normal, distance: hit.t })
+    }
+
+    /// Triangles touching a world space box: (node, triangle) pairs, see `MeshBvh::overlapping`
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn overlapping(&self, aabb: &Aabb) -> Vec<(usize, usize)> {
+        let mut result = Vec::new();
+        for i in self.objects.overlapping(aabb) {
+            let instance = &self.instances[i];
+            let mesh = &self.meshes[instance.mesh];
+            for triangle in mesh.overlapping(&aabb.transformed(&instance.inverse)) {
+                let world = mesh.triangle(triangle).map(|p| instance.world.apply(&p));
+                if Aabb::from_points(&world).overlaps(aabb) {
+                    result.push((instance.node, triangle));
+                }
+            }
+        }
+        result
+    }
+
+    /// Closest point of the scene to a world space point: (node, nearest point)
+    pub fn nearest_point(&self, point: &Vec3D, max_distance: f32) -> Option<(usize, NearestPoint)> {
+        self.objects.closest(max_distance, |b| Some(b.distance(point)), |i, limit| {
+            let instance = &self.instances[i];
+            self.meshes[instance.mesh].nearest_point_transformed(point, &instance.world, limit)
+                .map(|nearest| (nearest.distance, (instance.node, nearest)))
+        }).map(|(_, nearest)| nearest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::gfx::bvh::closest_point_on_triangle;
+    use std::collections::HashSet;
+
+    /// Two teapots, the second one scaled, turned and moved away from the first
+    fn teapots() -> Scene {
+        let mut scene = Scene::new();
+        let mesh = scene.add_mesh(Mesh::load_object("example_objs/teapot.obj").unwrap());
+        scene.add_node("a", None, Transform::identity(), Some(mesh));
+        let b = scene.add_node("b", None, Transform::from_translation(4.0, 1.0, -2.0), Some(mesh));
+        let transform = scene.transform_mut(b);
+        transform.rotation = Quaternion::from_euler(0.3, 1.2, 0.0);
+        transform.scale = Vec3D::new(0.5, 0.5, 0.5);
+        scene.update();
+        scene
+    }
+
+    /// Every triangle of the scene placed in the world: (node, triangle, vertices)
+    fn world_triangles(scene: &Scene) -> Vec<(usize, usize, [Vec3D; 3])> {
+        scene.drawables()
+            .flat_map(|(node, mesh, _)| (0..mesh.faces.len()).map(move |t| (node, t)))
+            .map(|(node, t)| (node, t, scene.world_triangle(node, t).unwrap()))
+            .collect()
+    }
+
+    /// Pseudo-random points around the scene
+    fn points(count: usize) -> Vec<Vec3D> {
+        let mut seed = 7u32;
+        let mut next = || {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (seed >> 8) as f32 / (1 << 24) as f32 * 12.0 - 4.0
+        };
+        (0..count).map(|_| Vec3D::new(next(), next(), next())).collect()
+    }
+
+    #[test]
+    fn overlapping_finds_the_triangles_in_the_box() {
+        let scene = teapots();
+        let bvh = SceneBvh::new(&scene);
+        let triangles = world_triangles(&scene);
+        for pair in points(100).chunks(2) {
+            let aabb = Aabb::from_points(pair);
+            let found: HashSet<(usize, usize)> = bvh.overlapping(&aabb).into_iter().collect();
+            // the world boxes of the results overlap the box, and a triangle with a vertex
+            // inside the box is always found
+            for (node, t, p) in &triangles {
+                let reported = found.contains(&(*node, *t));
+                if reported {
+                    assert!(Aabb::from_points(p).overlaps(&aabb));
+                }
+                if p.iter().any(|v| aabb.distance(v) == 0.0) {
+                    assert!(reported, "triangle {} of node {} is in the box", t, node);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nearest_point_matches_brute_force() {
+        let scene = teapots();
+        let bvh = SceneBvh::new(&scene);
+        let triangles = world_triangles(&scene);
+        for p in points(50) {
+            let expected = triangles.iter()
+                .map(|(_, _, t)| (closest_point_on_triangle(&p, t) - p).len())
+                .fold(f32::MAX, f32::min);
+            let (node, nearest) = bvh.nearest_point(&p, f32::MAX).unwrap();
+            assert!((nearest.distance - expected).abs() <= 1e-3 * expected.max(1.0));
+            let on_triangle = closest_point_on_triangle(&p, &scene.world_triangle(node, nearest.triangle).unwrap());
+            assert!((on_triangle - nearest.point).len() < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,1110 @@
+//! Text description of a scene: meshes placed by nodes, lights, the camera and render settings.
+//!
+//! The format is line based like OBJ and MTL: `#` starts a comment, every line is a keyword
+//! followed by its values. `settings`, `camera`, `node <name>`, `light <kind>`, `animation` and
+//! `track <target>` start a block, the lines after it set its properties until the next block.
+//! `import <name> <path>` loads a mesh on a single line, the path is relative to the scene file.
+//! Meshes and parents have to be declared before the nodes referring to them. Angles are in degrees, colours are `r g b` in 0..255.
+//!
+//! ```text
+//! settings
+//! size 640 480
+//! antialiasing msaa 4              # none, msaa <samples>, supersample <factor>
+//! background 0 0 0
+//! shading flat                     # flat, gouraud, phong, blinn-phong
+//! mode solid                       # solid, solid-wireframe, wireframe, hidden-line, points
+//! tiled on
+//! fog linear 75 150 150 170 190    # mode, start, end and an optional colour
+//! shadows 1024 1                   # map size (16..8192) and PCF radius (up to 8), or off
+//!
+//! camera
+//! position 0 0 0
+//! orientation 0 0 0                # yaw, pitch, roll
+//! fov 90
+//! clip 0.1 1000
+//! projection perspective           # or orthographic <half height>
+//!
+//! import ship space_ship.obj
+//!
+//! node ship
+//! parent root
+//! mesh ship
+//! translation 0 50 10
+//! euler 0 45 0                     # or rotation <w> <x> <y> <z>
+//! scale 2                          # uniform, or x y z
+//! material 0.1 0.9 0.5 32          # ambient, diffuse, specular, shininess
+//! opacity 1
+//! blend alpha                      # opaque, alpha, additive, multiply
+//! cull back                        # none, back, front
+//! winding ccw                      # vertex order of the front faces: ccw (like OBJ) or cw
+//!
+//! light spot                       # ambient, directional, point, spot
+//! color 255 255 255
+//! intensity 1
+//! position 0 40 10
+//! direction 0 -1 0
+//! range 120                        # or attenuation <constant> <linear> <quadratic>
+//! cone 15 30                       # inner and outer angle of a spot
+//! shadows                          # the light gets the shadow map, directional and spot only
+//!
+//! animation
+//! play on
+//! loop on
+//! speed 1
+//!
+//! track node ship rotation linear  # node <name> translation, rotation or scale
+//! key 0 0 0 0                      # time in seconds and the value: euler angles or w x y z here
+//! key 10 0 90 0
+//!
+//! track light 0 intensity cubic    # light <index> position, direction, color or intensity,
+//! key 0 1                          # camera position, yaw, pitch, roll or fov;
+//! key 5 0.2                        # step, linear or cubic
+//! ```
+//!
+//! Lights are numbered from 0 in the order of the file, tracks refer to the lights declared
+//! before them. Shadows in the settings need one of the lights to cast them, unless the file has
+//! no lights at all and the renderer keeps its own.
+
+use sdl2::pixels::Color;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use super::{Scene, Transform};
+use crate::naive::animation::{Animation, Channel, Interpolation, Target};
+use crate::naive::gfx::{Camera, Light, Mesh, Quaternion, ShadingMode, Vec3D};
+use crate::naive::gfx::camera::Projection;
+use crate::naive::gfx::culling::{CullMode, Winding};
+use crate::naive::gfx::fog::{Fog, FogMode};
+use crate::naive::gfx::light::{Attenuation, LightKind};
+use crate::naive::gfx::shading::{BlendMode, Material};
+use crate::naive::raster::{AntiAliasing, RenderMode};
+use crate::naive::render::Renderer;
+use crate::naive::shadow;
+
+/// Largest supersampling factor
+const MAX_SUPERSAMPLE: u32 = 4;
+/// Shadow map sizes, and the largest PCF radius: every lit fragment reads (2 * radius + 1)^2 texels
+const SHADOW_MAP_SIZES: std::ops::RangeInclusive<u32> = 16..=8192;
+const MAX_PCF: u32 = 8;
+/// Limits of the render target: pixels a side (supersampled) and samples in total
+const MAX_TARGET_SIDE: u64 = 8192;
+const MAX_TARGET_SAMPLES: u64 = 1 << 26;
+
+/// A scene file that couldn't be read or doesn't make sense
+#[derive(Debug)]
+pub enum SceneError {
+    Io(String),
+    /// A problem on a line of the file (counted from 1)
+    Invalid { line: usize, message: String },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(message) => write!(f, "{}", message),
+            SceneError::Invalid { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+/// How the scene gets rendered: the window and the renderer are set up from it
+#[derive(Copy, Clone, Debug)]
+pub struct Settings {
+    pub width: u32,
+    pub height: u32,
+    pub anti_aliasing: AntiAliasing,
+    pub background: Color,
+    pub shading: ShadingMode,
+    pub render_mode: RenderMode,
+    pub tiled: bool,
+    pub fog: Option<Fog>,
+    /// Size and PCF radius of the shadow map, no shadows when `None`
+    pub shadows: Option<(u32, u32)>
+}
+
+impl Settings {
+    pub fn default() -> Self {
+        Settings {
+            width: 640,
+            height: 480,
+            anti_aliasing: AntiAliasing::Msaa(4),
+            background: Color::RGBA(0, 0, 0, 255),
+            shading: ShadingMode::Flat,
+            render_mode: RenderMode::Solid,
+            tiled: true,
+            fog: None,
+            shadows: None
+        }
+    }
+
+    /// The current settings of a renderer
+    pub fn from_renderer(renderer: &Renderer) -> Self {
+        Settings {
+            width: renderer.buffer.width,
+            height: renderer.buffer.height,
+            anti_aliasing: renderer.buffer.anti_aliasing,
+            background: renderer.background,
+            shading: renderer.shading,
+            render_mode: renderer.render_mode,
+            tiled: renderer.tiled,
+            fog: renderer.fog,
+            shadows: renderer.shadow.as_ref().map(|s| (s.size, s.pcf))
+        }
+    }
+
+    /// Sets up a renderer, which should have been created with the size and the anti-aliasing.
+    /// The shadow map goes to a light of the renderer, so its lights have to be in place already.
+    /// Returns false when the settings have shadows but none of the lights casts them.
+    pub fn apply(&self, renderer: &mut Renderer) -> bool {
+        renderer.set_background(self.background);
+        renderer.shading = self.shading;
+        renderer.render_mode = self.render_mode;
+        renderer.tiled = self.tiled;
+        renderer.fog = self.fog;
+        match self.shadows {
+            Some((size, pcf)) => renderer.enable_shadows(size, pcf),
+            None => {
+                renderer.shadow = None;
+                true
+            },
+        }
+    }
+}
+
+/// A mesh of the scene as the file refers to it
+#[derive(Clone, Debug)]
+pub struct MeshSource {
+    pub name: String,
+    /// Where the mesh was loaded from, relative to the working directory or absolute.
+    /// Saving writes it relative to the new file.
+    pub path: String
+}
+
+/// Everything a scene file describes
+pub struct SceneFile {
+    pub scene: Scene,
+    /// Names and paths of `Scene::meshes`, in the same order
+    pub sources: Vec<MeshSource>,
+    pub camera: Camera,
+    pub lights: Vec<Light>,
+    pub settings: Settings,
+    pub animation: Animation
+}
+
+/// Reads and validates a scene file, then loads its meshes
+pub fn load(path: &str) -> Result<SceneFile, SceneError> {
+    let text = fs::read_to_string(path).map_err(|e| SceneError::Io(format!("{}: {}", path, e)))?;
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    parse(&text, dir)
+}
+
+/// Writes the current state of a scene, its camera, animation and the renderer settings and lights
+pub fn save(path: &str, scene: &Scene, sources: &[MeshSource], camera: &Camera, renderer: &Renderer,
+            animation: &Animation) -> Result<(), SceneError> {
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    let text = to_text(scene, sources, camera, &renderer.lights, &Settings::from_renderer(renderer), animation, dir);
+    fs::write(path, text).map_err(|e| SceneError::Io(format!("{}: {}", path, e)))
+}
+
+/// The block the following property lines belong to
+enum Block {
+    None,
+    Settings,
+    Camera,
+    Node(NodeDef),
+    Light(LightDef),
+    Animation,
+    Track(TrackDef),
+}
+
+struct NodeDef {
+    name: String,
+    parent: Option<usize>,
+    mesh: Option<usize>,
+    transform: Transform,
+    material: Option<Material>
+}
+
+struct LightDef {
+    line: usize,
+    kind: String,
+    color: Color,
+    intensity: f32,
+    position: Option<Vec3D>,
+    direction: Option<Vec3D>,
+    attenuation: Attenuation,
+    cone: (f32, f32),
+    casts_shadow: bool
+}
+
+struct TrackDef {
+    line: usize,
+    target: Target,
+    channel: Channel
+}
+
+/// Values of one line, converted with the line number at hand for the errors
+struct Line<'a> {
+    number: usize,
+    keyword: &'a str,
+    values: Vec<&'a str>
+}
+
+impl<'a> Line<'a> {
+    fn error<T>(&self, message: String) -> Result<T, SceneError> {
+        Err(SceneError::Invalid { line: self.number, message })
+    }
+
+    fn expect(&self, count: usize) -> Result<(), SceneError> {
+        if self.values.len() != count {
+            return self.error(format!("`{}` takes {} value(s), got {}", self.keyword, count, self.values.len()))
+        }
+        Ok(())
+    }
+
+    fn float(&self, i: usize) -> Result<f32, SceneError> {
+        match self.values.get(i).map(|v| v.parse::<f32>()) {
+            Some(Ok(v)) if v.is_finite() => Ok(v),
+            _ => self.error(format!("`{}` expects a number at position {}", self.keyword, i + 1)),
+        }
+    }
+
+    fn floats(&self, count: usize) -> Result<Vec<f32>, SceneError> {
+        self.expect(count)?;
+        (0..count).map(|i| self.float(i)).collect()
+    }
+
+    fn positive(&self, i: usize) -> Result<f32, SceneError> {
+        let v = self.float(i)?;
+        if v <= 0.0 {
+            return self.error(format!("`{}` expects a positive number", self.keyword))
+        }
+        Ok(v)
+    }
+
+    fn integer(&self, i: usize) -> Result<u32, SceneError> {
+        match self.values.get(i).map(|v| v.parse::<u32>()) {
+            Some(Ok(v)) if v > 0 => Ok(v),
+            _ => self.error(format!("`{}` expects a positive integer at position {}", self.keyword, i + 1)),
+        }
+    }
+
+    fn vector(&self) -> Result<Vec3D, SceneError> {
+        let v = self.floats(3)?;
+        Ok(Vec3D::new(v[0], v[1], v[2]))
+    }
+
+    fn direction(&self) -> Result<Vec3D, SceneError> {
+        let mut v = self.vector()?;
+        if v.len() == 0.0 {
+            return self.error("a direction can't be zero".to_string())
+        }
+        // saved directions are normalized already, normalizing again would drift them
+        if (v.len() - 1.0).abs() > 1e-6 {
+            v.normalize();
+        }
+        Ok(v)
+    }
+
+    /// `w x y z` of a rotation, normalized the same way as `direction`
+    fn rotation(&self) -> Result<Quaternion, SceneError> {
+        let v = self.floats(4)?;
+        let q = Quaternion { w: v[0], x: v[1], y: v[2], z: v[3] };
+        if q.len() == 0.0 {
+            return self.error("a rotation can't be a zero quaternion".to_string())
+        }
+        Ok(if (q.len() - 1.0).abs() > 1e-6 { q.normalized() } else { q })
+    }
+
+    /// `r g b` starting at the position
+    fn color(&self, i: usize) -> Result<Color, SceneError> {
+        let mut c = [0u8; 3];
+        for (k, channel) in c.iter_mut().enumerate() {
+            *channel = match self.values.get(i + k).map(|v| v.parse::<u8>()) {
+                Some(Ok(v)) => v,
+                _ => return self.error(format!("`{}` expects a colour as three values in 0..255", self.keyword)),
+            };
+        }
+        Ok(Color::RGBA(c[0], c[1], c[2], 255))
+    }
+
+    fn switch(&self) -> Result<bool, SceneError> {
+        self.expect(1)?;
+        match self.values[0] {
+            "on" => Ok(true),
+            "off" => Ok(false),
+            other => self.error(format!("expected `on` or `off`, got `{}`", other)),
+        }
+    }
+}
+
+/// Parses a scene description, meshes are loaded relative to `dir`
+pub fn parse(text: &str, dir: &Path) -> Result<SceneFile, SceneError> {
+    let mut file = SceneFile {
+        scene: Scene::new(),
+        sources: Vec::new(),
+        camera: Camera::new(Vec3D::init(), 90.0, 0.75, 0.1, 1000.0),
+        lights: Vec::new(),
+        settings: Settings::default(),
+        animation: Animation::new()
+    };
+    let mut nodes: HashMap<String, usize> = HashMap::new();
+    let mut meshes: HashMap<String, usize> = HashMap::new();
+    let mut block = Block::None;
+    let mut shadows_line = 0;
+
+    for (i, raw) in text.lines().enumerate() {
+        let content = raw.split('#').next().unwrap_or("");
+        let mut tokens = content.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) => k,
+            None => continue
+        };
+        let line = Line { number: i + 1, keyword, values: tokens.collect() };
+
+        match keyword {
+            "settings" | "camera" | "node" | "light" | "import" | "animation" | "track" => {
+                finish(std::mem::replace(&mut block, Block::None), &mut file, &mut nodes)?;
+            },
+            _ => (),
+        }
+        match keyword {
+            "settings" => {
+                line.expect(0)?;
+                block = Block::Settings;
+            },
+            "camera" => {
+                line.expect(0)?;
+                block = Block::Camera;
+            },
+            "animation" => {
+                line.expect(0)?;
+                block = Block::Animation;
+            },
+            "track" => {
+                let (target, interpolation) = track_header(&line, &nodes, file.lights.len())?;
+                block = Block::Track(TrackDef { line: line.number, target, channel: Channel::for_target(target, interpolation) });
+            },
+            "import" => {
+                if line.values.len() < 2 {
+                    return line.error("`import` takes a name and a path".to_string())
+                }
+                let name = line.values[0].to_string();
+                if meshes.contains_key(&name) {
+                    return line.error(format!("mesh `{}` is declared twice", name))
+                }
+                let path = line.values[1..].join(" ");
+                let full = dir.join(&path);
+                if !full.is_file() {
+                    return line.error(format!("mesh file `{}` not found", full.display()))
+                }
+                let mesh = match Mesh::load_object(&full.to_string_lossy()) {
+                    Ok(mesh) => mesh,
+                    Err(e) => return line.error(format!("can't load `{}`: {}", full.display(), e)),
+                };
+                let id = file.scene.add_mesh(mesh);
+                meshes.insert(name.clone(), id);
+                file.sources.push(MeshSource { name, path: full.to_string_lossy().into_owned() });
+            },
+            "node" => {
+                line.expect(1)?;
+                let name = line.values[0].to_string();
+                if nodes.contains_key(&name) {
+                    return line.error(format!("node `{}` is declared twice", name))
+                }
+                block = Block::Node(NodeDef { name, parent: None, mesh: None, transform: Transform::identity(), material: None });
+            },
+            "light" => {
+                line.expect(1)?;
+                match line.values[0] {
+                    "ambient" | "directional" | "point" | "spot" => (),
+                    other => return line.error(format!("unknown light `{}`", other)),
+                }
+                block = Block::Light(LightDef {
+                    line: line.number,
+                    kind: line.values[0].to_string(),
+                    color: Color::RGBA(255, 255, 255, 255),
+                    intensity: 1.0,
+                    position: None,
+                    direction: None,
+                    attenuation: Attenuation::range(100.0),
+                    cone: (15.0, 30.0),
+                    casts_shadow: false
+                });
+            },
+            _ => match &mut block {
+                Block::None => return line.error(format!("`{}` outside of a block", keyword)),
+                Block::Settings => {
+                    settings_property(&line, &mut file.settings)?;
+                    if keyword == "shadows" {
+                        shadows_line = line.number;
+                    }
+                },
+                Block::Camera => camera_property(&line, &mut file.camera)?,
+                Block::Node(node) => node_property(&line, node, &nodes, &meshes)?,
+                Block::Light(light) => light_property(&line, light)?,
+                Block::Animation => animation_property(&line, &mut file.animation)?,
+                Block::Track(track) => track_key(&line, track)?,
+            },
+        }
+    }
+    finish(block, &mut file, &mut nodes)?;
+
+    if file.settings.shadows.is_some() && !file.lights.is_empty() && shadow::shadow_caster(&file.lights).is_none() {
+        return Err(SceneError::Invalid {
+            line: shadows_line, message: "shadows need a directional or spot light marked with `shadows`".to_string()
+        })
+    }
+    file.camera.aspect_ratio = file.settings.height as f32 / file.settings.width as f32;
+    file.scene.update();
+    Ok(file)
+}
+
+/// Adds what a finished block describes
+fn finish(block: Block, file: &mut SceneFile, nodes: &mut HashMap<String, usize>) -> Result<(), SceneError> {
+    match block {
+        Block::Node(def) => {
+            let id = file.scene.add_node(&def.name, def.parent, def.transform, def.mesh);
+            file.scene.nodes[id].material = def.material;
+            nodes.insert(def.name, id);
+        },
+        Block::Light(def) => {
+            let missing = |what: &str| Err(SceneError::Invalid {
+                line: def.line, message: format!("{} light without a {}", def.kind, what)
+            });
+            let kind = match def.kind.as_str() {
+                "ambient" => LightKind::Ambient,
+                "directional" => match def.direction {
+                    Some(direction) => LightKind::Directional { direction },
+                    None => return missing("direction"),
+                },
+                "point" => match def.position {
+                    Some(position) => LightKind::Point { position, attenuation: def.attenuation },
+                    None => return missing("position"),
+                },
+                _ => match (def.position, def.direction) {
+                    (Some(position), Some(direction)) => LightKind::Spot {
+                        position, direction, inner: def.cone.0, outer: def.cone.1, attenuation: def.attenuation
+                    },
+                    (None, _) => return missing("position"),
+                    (_, None) => return missing("direction"),
+                },
+            };
+            let mut light = Light::new(kind, def.color, def.intensity);
+            light.casts_shadow = def.casts_shadow;
+            file.lights.push(light);
+        },
+        Block::Track(def) => {
+            if def.channel.is_empty() {
+                return Err(SceneError::Invalid { line: def.line, message: "a track without keys".to_string() })
+            }
+            file.animation.add(def.target, def.channel);
+        },
+        Block::None | Block::Settings | Block::Camera | Block::Animation => (),
+    }
+    Ok(())
+}
+
+/// Keeps the render target of the size and anti-aliasing settings within memory
+fn check_target(line: &Line, settings: &Settings) -> Result<(), SceneError> {
+    let (width, height) = (settings.width as u64, settings.height as u64);
+    let (factor, samples) = match settings.anti_aliasing {
+        AntiAliasing::Supersample(f) => (f as u64, 1),
+        AntiAliasing::Msaa(n) => (1, n as u64),
+        AntiAliasing::None => (1, 1),
+    };
+    // the sides first, the product can't overflow after them
+    if width * factor > MAX_TARGET_SIDE || height * factor > MAX_TARGET_SIDE ||
+        width * height * factor * factor * samples > MAX_TARGET_SAMPLES {
+        return line.error(format!("a {}x{} frame with this anti-aliasing is too large: the render target is limited to \
+                                   {} pixels a side and {} samples", width, height, MAX_TARGET_SIDE, MAX_TARGET_SAMPLES))
+    }
+    Ok(())
+}
+
+fn settings_property(line: &Line, settings: &mut Settings) -> Result<(), SceneError> {
+    match line.keyword {
+        "size" => {
+            line.expect(2)?;
+            settings.width = line.integer(0)?;
+            settings.height = line.integer(1)?;
+            check_target(line, settings)?;
+        },
+        "antialiasing" => {
+            settings.anti_aliasing = match line.values.first().copied() {
+                Some("none") => { line.expect(1)?; AntiAliasing::None },
+                Some("msaa") => {
+                    line.expect(2)?;
+                    match line.integer(1)? {
+                        n @ 1 | n @ 2 | n @ 4 | n @ 8 => AntiAliasing::Msaa(n),
+                        _ => return line.error("MSAA takes 1, 2, 4 or 8 samples".to_string()),
+                    }
+                },
+                Some("supersample") => {
+                    line.expect(2)?;
+                    match line.integer(1)? {
+                        f if f <= MAX_SUPERSAMPLE => AntiAliasing::Supersample(f),
+                        _ => return line.error(format!("supersampling goes up to a factor of {}", MAX_SUPERSAMPLE)),
+                    }
+                },
+                _ => return line.error("expected `none`, `msaa <samples>` or `supersample <factor>`".to_string()),
+            };
+            check_target(line, settings)?;
+        },
+        "background" => {
+            line.expect(3)?;
+            settings.background = line.color(0)?;
+        },
+        "shading" => {
+            line.expect(1)?;
+            settings.shading = match line.values[0] {
+                "flat" => ShadingMode::Flat,
+                "gouraud" => ShadingMode::Gouraud,
+                "phong" => ShadingMode::Phong,
+                "blinn-phong" => ShadingMode::BlinnPhong,
+                other => return line.error(format!("unknown shading `{}`", other)),
+            };
+        },
+        "mode" => {
+            line.expect(1)?;
+            settings.render_mode = match line.values[0] {
+                "solid" => RenderMode::Solid,
+                "solid-wireframe" => RenderMode::SolidWireframe,
+                "wireframe" => RenderMode::Wireframe,
+                "hidden-line" => RenderMode::HiddenLine,
+                "points" => RenderMode::Points,
+                other => return line.error(format!("unknown render mode `{}`", other)),
+            };
+        },
+        "tiled" => settings.tiled = line.switch()?,
+        "fog" => {
+            if line.values.first() == Some(&"off") {
+                line.expect(1)?;
+                settings.fog = None;
+                return Ok(())
+            }
+            if line.values.len() != 3 && line.values.len() != 6 {
+                return line.error("`fog` takes a mode, start, end and an optional colour".to_string())
+            }
+            let mode = match line.values[0] {
+                "linear" => FogMode::Linear,
+                "exponential" => FogMode::Exponential,
+                "exponential-squared" => FogMode::ExponentialSquared,
+                other => return line.error(format!("unknown fog mode `{}`", other)),
+            };
+            let (start, end) = (line.float(1)?, line.positive(2)?);
+            if start >= end {
+                return line.error("the fog has to start before its end".to_string())
+            }
+            let color = if line.values.len() == 6 { line.color(3)? } else { settings.background };
+            settings.fog = Some(Fog::new(mode, color, start, end));
+        },
+        "shadows" => {
+            settings.shadows = match line.values.as_slice() {
+                ["off"] => None,
+                [_] => Some((line.integer(0)?, 1)),
+                [_, pcf] => Some((line.integer(0)?, pcf.parse::<u32>().or_else(|_| line.error("bad PCF radius".to_string()))?)),
+                _ => return line.error("`shadows` takes a map size and a PCF radius, or `off`".to_string()),
+            };
+            if let Some((size, pcf)) = settings.shadows {
+                if !SHADOW_MAP_SIZES.contains(&size) {
+                    return line.error(format!("the shadow map size has to be in {}..{}",
+                                              SHADOW_MAP_SIZES.start(), SHADOW_MAP_SIZES.end()))
+                }
+                if pcf > MAX_PCF {
+                    return line.error(format!("the PCF radius goes up to {}", MAX_PCF))
+                }
+            }
+        },
+        other => return line.error(format!("unknown setting `{}`", other)),
+    }
+    Ok(())
+}
+
+fn camera_property(line: &Line, camera: &mut Camera) -> Result<(), SceneError> {
+    match line.keyword {
+        "position" => camera.position = line.vector()?,
+        "orientation" => {
+            let v = line.floats(3)?;
+            camera.yaw = v[0].to_radians();
+            camera.pitch = 0.0;
+            camera.pitch(v[1].to_radians());
+            camera.roll = v[2].to_radians();
+        },
+        "fov" => {
+            line.expect(1)?;
+            let fov = line.positive(0)?;
+            if fov >= 180.0 {
+                return line.error("the field of view has to be under 180 degrees".to_string())
+            }
+            camera.fov = fov;
+        },
+        "clip" => {
+            line.expect(2)?;
+            let (near, far) = (line.positive(0)?, line.positive(1)?);
+            if near >= far {
+                return line.error("the near plane has to be closer than the far one".to_string())
+            }
+            camera.near = near;
+            camera.far = far;
+        },
+        "projection" => match line.values.as_slice() {
+            ["perspective"] => camera.projection = Projection::Perspective,
+            ["orthographic", _] => {
+                camera.projection = Projection::Orthographic;
+                camera.ortho_size = line.positive(1)?;
+            },
+            _ => return line.error("expected `perspective` or `orthographic <half height>`".to_string()),
+        },
+        other => return line.error(format!("unknown camera property `{}`", other)),
+    }
+    Ok(())
+}
+
+fn node_property(line: &Line, node: &mut NodeDef, nodes: &HashMap<String, usize>,
+                 meshes: &HashMap<String, usize>) -> Result<(), SceneError> {
+    match line.keyword {
+        "parent" => {
+            line.expect(1)?;
+            node.parent = match nodes.get(line.values[0]) {
+                Some(&id) => Some(id),
+                None => return line.error(format!("unknown parent `{}`, parents go before their children", line.values[0])),
+            };
+        },
+        "mesh" => {
+            line.expect(1)?;
+            node.mesh = match meshes.get(line.values[0]) {
+                Some(&id) => Some(id),
+                None => return line.error(format!("unknown mesh `{}`", line.values[0])),
+            };
+        },
+        "translation" => node.transform.translation = line.vector()?,
+        "rotation" => node.transform.rotation = line.rotation()?,
+        "euler" => {
+            let v = line.floats(3)?;
+            node.transform.rotation = Quaternion::from_euler(v[0].to_radians(), v[1].to_radians(), v[2].to_radians());
+        },
+        "scale" => {
+            node.transform.scale = match line.values.len() {
+                1 => { let s = line.float(0)?; Vec3D::new(s, s, s) },
+                _ => line.vector()?,
+            };
+        },
+        "material" => {
+            let v = line.floats(4)?;
+            let m = node.material.get_or_insert_with(Material::default);
+            m.ambient = v[0];
+            m.diffuse = v[1];
+            m.specular = v[2];
+            m.shininess = v[3];
+        },
+        "opacity" => {
+            line.expect(1)?;
+            let opacity = line.float(0)?;
+            if !(0.0..=1.0).contains(&opacity) {
+                return line.error("the opacity has to be in 0..1".to_string())
+            }
+            let m = node.material.get_or_insert_with(Material::default);
+            m.opacity = opacity;
+            // like the MTL dissolve, unless `blend` picked another mode
+            if m.blend == BlendMode::Opaque || m.blend == BlendMode::Alpha {
+                m.blend = if opacity < 1.0 { BlendMode::Alpha } else { BlendMode::Opaque };
+            }
+        },
+        "blend" => {
+            line.expect(1)?;
+            let blend = match line.values[0] {
+                "opaque" => BlendMode::Opaque,
+                "alpha" => BlendMode::Alpha,
+                "additive" => BlendMode::Additive,
+                "multiply" => BlendMode::Multiply,
+                other => return line.error(format!("unknown blend mode `{}`", other)),
+            };
+            node.material.get_or_insert_with(Material::default).blend = blend;
+        },
+        "cull" => {
+            line.expect(1)?;
+            let cull = match line.values[0] {
+                "none" => CullMode::None,
+                "back" => CullMode::Back,
+                "front" => CullMode::Front,
+                other => return line.error(format!("unknown cull mode `{}`", other)),
+            };
+            node.material.get_or_insert_with(Material::default).cull = cull;
+        },
+        "winding" => {
+            line.expect(1)?;
+            let winding = match line.values[0] {
+                "ccw" => Winding::CounterClockwise,
+                "cw" => Winding::Clockwise,
+                other => return line.error(format!("unknown winding `{}`", other)),
+            };
+            node.material.get_or_insert_with(Material::default).winding = winding;
+        },
+        other => return line.error(format!("unknown node property `{}`", other)),
+    }
+    Ok(())
+}
+
+fn light_property(line: &Line, light: &mut LightDef) -> Result<(), SceneError> {
+    match line.keyword {
+        "color" => {
+            line.expect(3)?;
+            light.color = line.color(0)?;
+        },
+        "intensity" => {
+            line.expect(1)?;
+            light.intensity = line.float(0)?.max(0.0);
+        },
+        "position" => light.position = Some(line.vector()?),
+        "direction" => light.direction = Some(line.direction()?),
+        "range" => {
+            line.expect(1)?;
+            light.attenuation = Attenuation::range(line.positive(0)?);
+        },
+        "attenuation" => {
+            let v = line.floats(3)?;
+            if v.iter().any(|&a| a < 0.0) || v.iter().all(|&a| a == 0.0) {
+                return line.error("the attenuation factors can't be negative or all zero".to_string())
+            }
+            light.attenuation = Attenuation { constant: v[0], linear: v[1], quadratic: v[2] };
+        },
+        "cone" => {
+            let v = line.floats(2)?;
+            if v[0] < 0.0 || v[0] > v[1] || v[1] >= 90.0 {
+                return line.error("the cone takes an inner and a larger outer angle, under 90 degrees".to_string())
+            }
+            light.cone = (v[0], v[1]);
+        },
+        "shadows" => {
+            line.expect(0)?;
+            light.casts_shadow = true;
+        },
+        other => return line.error(format!("unknown light property `{}`", other)),
+    }
+    Ok(())
+}
+
+fn animation_property(line: &Line, animation: &mut Animation) -> Result<(), SceneError> {
+    match line.keyword {
+        "play" => animation.playing = line.switch()?,
+        "loop" => animation.looping = line.switch()?,
+        "speed" => {
+            line.expect(1)?;
+            animation.speed = line.float(0)?;
+        },
+        other => return line.error(format!("unknown animation property `{}`", other)),
+    }
+    Ok(())
+}
+
+/// `track <target> [interpolation]`
+fn track_header(line: &Line, nodes: &HashMap<String, usize>, lights: usize) -> Result<(Target, Interpolation), SceneError> {
+    let (target, rest) = match line.values.as_slice() {
+        ["node", name, property, rest @ ..] => {
+            let node = match nodes.get(*name) {
+                Some(&id) => id,
+                None => return line.error(format!("unknown node `{}`", name)),
+            };
+            (match *property {
+                "translation" => Target::NodeTranslation(node),
+                "rotation" => Target::NodeRotation(node),
+                "scale" => Target::NodeScale(node),
+                other => return line.error(format!("nodes can't animate `{}`", other)),
+            }, rest)
+        },
+        ["camera", property, rest @ ..] => (match *property {
+            "position" => Target::CameraPosition,
+            "yaw" => Target::CameraYaw,
+            "pitch" => Target::CameraPitch,
+            "roll" => Target::CameraRoll,
+            "fov" => Target::CameraFov,
+            other => return line.error(format!("the camera can't animate `{}`", other)),
+        }, rest),
+        ["light", index, property, rest @ ..] => {
+            let light = match index.parse::<usize>() {
+                Ok(i) if i < lights => i,
+                _ => return line.error(format!("no light `{}`, {} declared so far", index, lights)),
+            };
+            (match *property {
+                "position" => Target::LightPosition(light),
+                "direction" => Target::LightDirection(light),
+                "color" => Target::LightColor(light),
+                "intensity" => Target::LightIntensity(light),
+                other => return line.error(format!("lights can't animate `{}`", other)),
+            }, rest)
+        },
+        _ => return line.error("`track` takes `node <name>`, `camera` or `light <index>` and a property".to_string()),
+    };
+    let interpolation = match rest {
+        [] | ["linear"] => Interpolation::Linear,
+        ["step"] => Interpolation::Step,
+        ["cubic"] => Interpolation::Cubic,
+        _ => return line.error("expected `step`, `linear` or `cubic` after the property".to_string()),
+    };
+    Ok((target, interpolation))
+}
+
+/// `key <time> <value>`, keys go in time order
+fn track_key(line: &Line, track: &mut TrackDef) -> Result<(), SceneError> {
+    if line.keyword != "key" {
+        return line.error(format!("unknown track property `{}`", line.keyword))
+    }
+    let time = line.float(0)?;
+    if time < 0.0 || (!track.channel.is_empty() && time <= track.channel.end()) {
+        return line.error("key times can't be negative and have to increase".to_string())
+    }
+    let values = Line { number: line.number, keyword: line.keyword, values: line.values[1..].to_vec() };
+    match &mut track.channel {
+        Channel::Scalar(keys) => {
+            values.expect(1)?;
+            let v = values.float(0)?;
+            let v = match track.target {
+                Target::CameraYaw | Target::CameraPitch | Target::CameraRoll => v.to_radians(),
+                _ => v,
+            };
+            keys.insert(time, v);
+        },
+        Channel::Vector(keys) => keys.insert(time, values.vector()?),
+        Channel::Rotation(keys) => {
+            let q = match values.values.len() {
+                3 => {
+                    let v = values.floats(3)?;
+                    Quaternion::from_euler(v[0].to_radians(), v[1].to_radians(), v[2].to_radians())
+                },
+                _ => values.rotation()?,
+            };
+            keys.insert(time, q);
+        },
+    }
+    Ok(())
+}
+
+/// Writes a scene in the file format, `parse` reads it back to the same state.
+/// Mesh paths are written relative to `dir`, the directory of the file the text goes to.
+pub fn to_text(scene: &Scene, sources: &[MeshSource], camera: &Camera, lights: &[Light], settings: &Settings,
+               animation: &Animation, dir: &Path) -> String {
+    let mut out = String::from("# naive scene\n\nsettings\n");
+    let color = |c: &Color| format!("{} {} {}", c.r, c.g, c.b);
+    let vector = |v: &Vec3D| format!("{} {} {}", v.x, v.y, v.z);
+
+    out += &format!("size {} {}\n", settings.width, settings.height);
+    out += &match settings.anti_aliasing {
+        AntiAliasing::None => "antialiasing none\n".to_string(),
+        AntiAliasing::Msaa(n) => format!("antialiasing msaa {}\n", n),
+        AntiAliasing::Supersample(n) => format!("antialiasing supersample {}\n", n),
+    };
+    out += &format!("background {}\n", color(&settings.background));
+    out += match settings.shading {
+        ShadingMode::Flat => "shading flat\n",
+        ShadingMode::Gouraud => "shading gouraud\n",
+        ShadingMode::Phong => "shading phong\n",
+        ShadingMode::BlinnPhong => "shading blinn-phong\n",
+    };
+    out += match settings.render_mode {
+        RenderMode::Solid => "mode solid\n",
+        RenderMode::SolidWireframe => "mode solid-wireframe\n",
+        RenderMode::Wireframe => "mode wireframe\n",
+        RenderMode::HiddenLine => "mode hidden-line\n",
+        RenderMode::Points => "mode points\n",
+    };
+    out += if settings.tiled { "tiled on\n" } else { "tiled off\n" };
+    if let Some(fog) = settings.fog {
+        let mode = match fog.mode {
+            FogMode::Linear => "linear",
+            FogMode::Exponential => "exponential",
+            FogMode::ExponentialSquared => "exponential-squared",
+        };
+        out += &format!("fog {} {} {} {}\n", mode, fog.start, fog.end, color(&fog.color));
+    }
+    if let Some((size, pcf)) = settings.shadows {
+        out += &format!("shadows {} {}\n", size, pcf);
+    }
+
+    out += "\ncamera\n";
+    out += &format!("position {}\n", vector(&camera.position));
+    out += &format!("orientation {} {} {}\n", camera.yaw.to_degrees(), camera.pitch.to_degrees(), camera.roll.to_degrees());
+    out += &format!("fov {}\nclip {} {}\n", camera.fov, camera.near, camera.far);
+    out += &match camera.projection {
+        Projection::Perspective => "projection perspective\n".to_string(),
+        Projection::Orthographic => format!("projection orthographic {}\n", camera.ortho_size),
+    };
+
+    out += "\n";
+    for source in sources {
+        out += &format!("import {} {}\n", source.name, relative_path(Path::new(&source.path), dir));
+    }
+
+    for node in &scene.nodes {
+        out += &format!("\nnode {}\n", node.name);
+        if let Some(parent) = node.parent {
+            out += &format!("parent {}\n", scene.nodes[parent].name);
+        }
+        if let Some(source) = node.mesh.and_then(|m| sources.get(m)) {
+            out += &format!("mesh {}\n", source.name);
+        }
+        let t = node.transform();
+        let q = &t.rotation;
+        out += &format!("translation {}\nrotation {} {} {} {}\nscale {}\n", vector(&t.translation), q.w, q.x, q.y, q.z, vector(&t.scale));
+        if let Some(m) = &node.material {
+            out += &format!("material {} {} {} {}\nopacity {}\n", m.ambient, m.diffuse, m.specular, m.shininess, m.opacity);
+            out += match m.blend {
+                BlendMode::Opaque => "blend opaque\n",
+                BlendMode::Alpha => "blend alpha\n",
+                BlendMode::Additive => "blend additive\n",
+                BlendMode::Multiply => "blend multiply\n",
+            };
+            out += match m.cull {
+                CullMode::None => "cull none\n",
+                CullMode::Back => "cull back\n",
+                CullMode::Front => "cull front\n",
+            };
+            out += match m.winding {
+                Winding::CounterClockwise => "winding ccw\n",
+                Winding::Clockwise => "winding cw\n",
+            };
+        }
+    }
+
+    for light in lights {
+        let attenuation = |a: &Attenuation| format!("attenuation {} {} {}\n", a.constant, a.linear, a.quadratic);
+        out += &match light.kind {
+            LightKind::Ambient => "\nlight ambient\n".to_string(),
+            LightKind::Directional { direction } => format!("\nlight directional\ndirection {}\n", vector(&direction)),
+            LightKind::Point { position, attenuation: a } =>
+                format!("\nlight point\nposition {}\n{}", vector(&position), attenuation(&a)),
+            LightKind::Spot { position, direction, inner, outer, attenuation: a } =>
+                format!("\nlight spot\nposition {}\ndirection {}\ncone {} {}\n{}",
+                        vector(&position), vector(&direction), inner, outer, attenuation(&a)),
+        };
+        out += &format!("color {}\nintensity {}\n", color(&light.color), light.intensity);
+        if light.casts_shadow {
+            out += "shadows\n";
+        }
+    }
+
+    if animation.tracks.is_empty() {
+        return out
+    }
+    let switch = |on: bool| if on { "on" } else { "off" };
+    out += &format!("\nanimation\nplay {}\nloop {}\nspeed {}\n", switch(animation.playing), switch(animation.looping), animation.speed);
+    for (target, channel) in &animation.tracks {
+        let header = match *target {
+            Target::NodeTranslation(n) => format!("node {} translation", scene.nodes[n].name),
+            Target::NodeRotation(n) => format!("node {} rotation", scene.nodes[n].name),
+            Target::NodeScale(n) => format!("node {} scale", scene.nodes[n].name),
+            Target::CameraPosition => "camera position".to_string(),
+            Target::CameraYaw => "camera yaw".to_string(),
+            Target::CameraPitch => "camera pitch".to_string(),
+            Target::CameraRoll => "camera roll".to_string(),
+            Target::CameraFov => "camera fov".to_string(),
+            Target::LightPosition(i) => format!("light {} position", i),
+            Target::LightDirection(i) => format!("light {} direction", i),
+            Target::LightColor(i) => format!("light {} color", i),
+            Target::LightIntensity(i) => format!("light {} intensity", i),
+        };
+        let interpolation = match channel.interpolation() {
+            Interpolation::Step => "step",
+            Interpolation::Linear => "linear",
+            Interpolation::Cubic => "cubic",
+        };
+        out += &format!("\ntrack {} {}\n", header, interpolation);
+        match channel {
+            Channel::Scalar(track) => for k in &track.keys {
+                let v = match target {
+                    Target::CameraYaw | Target::CameraPitch | Target::CameraRoll => k.value.to_degrees(),
+                    _ => k.value,
+                };
+                out += &format!("key {} {}\n", k.time, v);
+            },
+            Channel::Vector(track) => for k in &track.keys {
+                out += &format!("key {} {}\n", k.time, vector(&k.value));
+            },
+            Channel::Rotation(track) => for k in &track.keys {
+                let q = &k.value;
+                out += &format!("key {} {} {} {} {}\n", k.time, q.w, q.x, q.y, q.z);
+            },
+        }
+    }
+    out
+}
+
+/// `path` as seen from `dir`: relative when both resolve to the same root, absolute otherwise.
+/// A path that can't be resolved is kept as it is.
+fn relative_path(path: &Path, dir: &Path) -> String {
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let (path, dir) = match (path.canonicalize(), dir.canonicalize()) {
+        (Ok(path), Ok(dir)) => (path, dir),
+        _ => return path.to_string_lossy().into_owned(),
+    };
+    let common = path.components().zip(dir.components()).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return path.to_string_lossy().into_owned()
+    }
+    let mut relative = PathBuf::new();
+    for _ in common..dir.components().count() {
+        relative.push("..");
+    }
+    relative.extend(path.components().skip(common));
+    relative.to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_of(file: &SceneFile, dir: &Path) -> String {
+        to_text(&file.scene, &file.sources, &file.camera, &file.lights, &file.settings, &file.animation, dir)
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let dir = Path::new("example_objs");
+        let mut file = load("example_objs/demo.scene").unwrap();
+        // a clockwise wound model has to come back clockwise
+        let ship = file.scene.nodes.iter().position(|n| n.name == "ship").unwrap();
+        file.scene.nodes[ship].material = Some(Material { winding: Winding::Clockwise, ..Material::default() });
+        let text = text_of(&file, dir);
+        let reloaded = parse(&text, dir).unwrap();
+        assert_eq!(reloaded.scene.nodes.len(), file.scene.nodes.len());
+        assert_eq!(reloaded.scene.nodes[ship].material.map(|m| m.winding), Some(Winding::Clockwise));
+        assert_eq!(reloaded.animation.tracks.len(), file.animation.tracks.len());
+        assert_eq!(text_of(&reloaded, dir), text);
+    }
+
+    #[test]
+    fn imports_are_saved_relative_to_the_file() {
+        // a mesh opened from the working directory, saved into another directory
+        let mut scene = Scene::new();
+        let mesh = scene.add_mesh(Mesh::load_object("example_objs/cube.obj").unwrap());
+        scene.add_node("cube", None, Transform::identity(), Some(mesh));
+        let file = SceneFile {
+            scene,
+            sources: vec![MeshSource { name: "cube".to_string(), path: "example_objs/cube.obj".to_string() }],
+            camera: Camera::new(Vec3D::init(), 90.0, 0.75, 0.1, 1000.0),
+            lights: Vec::new(),
+            settings: Settings::default(),
+            animation: Animation::new()
+        };
+        let dir = Path::new("example_objs");
+        let text = text_of(&file, dir);
+        assert!(text.contains("import cube cube.obj\n"));
+        assert_eq!(parse(&text, dir).unwrap().scene.meshes.len(), 1);
+        assert!(text_of(&file, Path::new("src")).contains("import cube ../example_objs/cube.obj\n"));
+    }
+
+    #[test]
+    fn shadows_need_a_casting_light() {
+        let text = "settings\nshadows 1024 1\n\nlight directional\ndirection 0 1 0\n";
+        match parse(text, Path::new("")) {
+            Err(SceneError::Invalid { line, .. }) => assert_eq!(line, 2),
+            _ => panic!("shadows without a casting light were accepted"),
+        }
+        assert!(parse(&format!("{}shadows\n", text), Path::new("")).is_ok());
+        // the renderer's own sun casts them when the file has no lights
+        assert!(parse("settings\nshadows 1024 1\n", Path::new("")).is_ok());
+        assert!(parse("settings\nshadows 70000 1\n", Path::new("")).is_err());
+        assert!(parse("settings\nshadows 1024 100\n", Path::new("")).is_err());
+    }
+}
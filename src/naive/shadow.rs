@@ -0,0 +1,147 @@
+use sdl2::pixels::Color;
+use super::gfx::{clip, Light, Matrix, Mesh, Triangle, Vec3D};
+use super::gfx::light::LightKind;
+use super::gfx::vector;
+use super::raster::FrameBuffer;
+
+/// How a shadow casting light sees the scene
+#[derive(Copy, Clone, Debug)]
+pub enum LightProjection {
+    /// Parallel rays, `direction` points towards the light (like a directional `Light`).
+    /// The map covers a sphere of `radius` around `center`, which should enclose the casters
+    /// (see `Mesh::bounding_sphere`).
+    Directional { direction: Vec3D, center: Vec3D, radius: f32 },
+    /// A cone of `fov` degrees from `position` along `direction`, up to `range`
+    Spot { position: Vec3D, direction: Vec3D, fov: f32, range: f32 },
+}
+
+impl LightProjection {
+    /// How a light sees a scene enclosed by the sphere (center, radius): a directional light
+    /// covers the sphere, a spot light its cone up to the far side of the sphere.
+    /// Point and ambient lights can't cast shadows.
+    pub fn for_light(light: &Light, center: Vec3D, radius: f32) -> Option<LightProjection> {
+        match light.kind {
+            LightKind::Directional { direction } => Some(LightProjection::Directional { direction, center, radius }),
+            LightKind::Spot { position, direction, outer, .. } => Some(LightProjection::Spot {
+                position,
+                direction,
+                fov: (2.0 * outer).min(170.0),
+                range: ((center - position).len() + radius).max(1.0)
+            }),
+            LightKind::Point { .. } | LightKind::Ambient => None,
+        }
+    }
+
+    /// Returns the world to light clip space matrix
+    pub fn matrix(&self) -> Matrix {
+        match *self {
+            LightProjection::Directional { direction, center, radius } => {
+                let mut dir = direction;
+                dir.normalize();
+                let eye = center + vector::vec_mul_by(&dir, radius);
+                let view = Matrix::point_at(&eye, &center, &up_for(&dir)).quick_inverse();
+                view * Matrix::init_orthographic(radius, 1.0, 0.0, 2.0 * radius)
+            },
+            LightProjection::Spot { position, direction, fov, range } => {
+                let mut dir = direction;
+                dir.normalize();
+                let view = Matrix::point_at(&position, &(position + dir), &up_for(&dir)).quick_inverse();
+                view * Matrix::init_projection(fov, 1.0, (range * 0.001).max(0.01), range)
+            },
+        }
+    }
+}
+
+/// Index of the light the shadow map belongs to: the first directional or spot light casting shadows
+pub fn shadow_caster(lights: &[Light]) -> Option<usize> {
+    lights.iter().position(|l| {
+        l.casts_shadow && matches!(l.kind, LightKind::Directional { .. } | LightKind::Spot { .. })
+    })
+}
+
+/// Any up vector will do for the light, as long as it isn't parallel to the direction
+fn up_for(direction: &Vec3D) -> Vec3D {
+    if direction.y.abs() > 0.99 { Vec3D::new(0.0, 0.0, 1.0) } else { Vec3D::new(0.0, 1.0, 0.0) }
+}
+
+/// Depth of the scene as seen from a light.
+/// Casters are rendered into it with `cast` at the start of a frame, then `visibility` tells
+/// how much of a world space point is lit: the depth of the point in the light space is compared
+/// with the stored one, `bias` is subtracted first to avoid self-shadowing ("shadow acne").
+/// With `pcf` > 0 the comparison is done over a (2 * pcf + 1)^2 texel area and averaged
+/// (percentage-closer filtering), which softens the shadow edges.
+pub struct ShadowMap {
+    /// Index of the light casting the shadow, only that light gets darkened by the map.
+    /// `Renderer::enable_shadows` picks it with `shadow_caster`.
+    pub caster: usize,
+    pub light: LightProjection,
+    pub bias: f32,
+    pub pcf: u32,
+    pub size: u32,
+    depth: FrameBuffer,
+    matrix: Matrix
+}
+
+impl ShadowMap {
+    pub fn new(light: LightProjection, size: u32) -> Self {
+        ShadowMap {
+            caster: 0,
+            light,
+            bias: 0.005,
+            pcf: 1,
+            size,
+            depth: FrameBuffer::new(size, size),
+            matrix: light.matrix()
+        }
+    }
+
+    /// Clears the map and picks up the current light settings
+    pub fn begin(&mut self) {
+        self.matrix = self.light.matrix();
+        self.depth.clear(Color::RGBA(0, 0, 0, 255));
+    }
+
+    /// Renders the depth of a mesh placed by `transform`. Both sides of the faces cast shadows.
+    pub fn cast(&mut self, mesh: &Mesh, transform: &Matrix) {
+        let size = self.size as f32;
+        let clip_positions = (*transform * self.matrix).apply_batch(&mesh.positions);
+        let mut region = self.depth.region();
+        for (face, tri) in mesh.faces.iter().zip(mesh.tris.iter()) {
+            let t_projected = Triangle { p: face.map(|v| clip_positions.get(v)), ..*tri };
+            for mut projection in clip::clip_triangle(&t_projected) {
+                projection.normalize();
+                projection.map_to_viewport(size, size);
+                region.fill_triangle(&projection, |_| tri.color);
+            }
+        }
+    }
+
+    /// Returns the lit fraction of a world space point: 1.0 is fully lit, 0.0 is in the shadow.
+    /// Points outside of the light frustum are lit.
+    pub fn visibility(&self, point: &Vec3D) -> f32 {
+        let q = self.matrix.apply(point);
+        if q.w <= 0.0 {
+            return 1.0
+        }
+        let depth = q.z / q.w;
+        if !(0.0..=1.0).contains(&depth) {
+            return 1.0
+        }
+        let size = self.size as i64;
+        let x = ((q.x / q.w + 1.0) * 0.5 * self.size as f32).floor() as i64;
+        let y = ((q.y / q.w + 1.0) * 0.5 * self.size as f32).floor() as i64;
+        let r = self.pcf as i64;
+
+        let mut lit = 0;
+        for ty in y - r..=y + r {
+            for tx in x - r..=x + r {
+                let inside = tx >= 0 && ty >= 0 && tx < size && ty < size;
+                if !inside || depth - self.bias <= self.depth.depth[(ty * size + tx) as usize] {
+                    lit += 1;
+                }
+            }
+        }
+        lit as f32 / ((2 * r + 1) * (2 * r + 1)) as f32
+    }
+}
+
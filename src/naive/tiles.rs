@@ -0,0 +1,200 @@
+use std::thread;
+use super::gfx::Triangle;
+use super::raster::{self, FrameBuffer, Region};
+
+/// A row of tiles handed to a worker: the tile row, its pixels and depths
+type Band<'a> = (i64, &'a mut [u32], &'a mut [f32]);
+
+/// Spreads the frame over worker threads: triangles are processed in chunks and then binned
+/// into square screen tiles which get rasterized in parallel.
+/// Every pixel is owned by exactly one worker and each tile draws its triangles in the
+/// submitted order, so the output is identical to the single-threaded path.
+/// Starting a thread costs more than processing a few triangles, so small jobs use fewer
+/// threads (at least `min_chunk` items each) and the smallest run on the calling thread.
+pub struct TileRenderer {
+    pub tile_size: u32,
+    pub threads: usize,
+    pub min_chunk: usize
+}
+
+impl TileRenderer {
+    /// Creates a renderer using all of the available cores
+    pub fn new(tile_size: u32) -> Self {
+        let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        TileRenderer { tile_size: tile_size.max(1), threads, min_chunk: 256 }
+    }
+
+    /// Number of threads worth using for `count` items
+    fn workers(&self, count: usize) -> usize {
+        count.div_ceil(self.min_chunk.max(1)).clamp(1, self.threads.max(1))
+    }
+
+    /// Runs `process` over every item (and its index) on the worker threads.
+    /// The outputs are concatenated in the order of the input items.
+    pub fn process<T, U, F>(&self, items: &[T], process: F) -> Vec<U>
+        where T: Sync, U: Send, F: Fn(usize, &T, &mut Vec<U>) + Sync {
+        self.process_indices(items.len(), |i, out| process(i, &items[i], out))
+    }
+
+    /// Runs `process` for every index in `0..count` on the worker threads.
+    /// The outputs are concatenated in the index order.
+    pub fn process_indices<U, F>(&self, count: usize, process: F) -> Vec<U>
+        where U: Send, F: Fn(usize, &mut Vec<U>) + Sync {
+        let workers = self.workers(count);
+        if workers == 1 {
+            let mut out = Vec::new();
+            for i in 0..count {
+                process(i, &mut out);
+            }
+            return out
+        }
+        let chunk_size = count.div_ceil(workers);
+        let process = &process;
+        let chunks: Vec<Vec<U>> = thread::scope(|s| {
+            let handles: Vec<_> = (0..count).step_by(chunk_size).map(|start| {
+                s.spawn(move || {
+                    let mut out = Vec::new();
+                    for i in start..(start + chunk_size).min(count) {
+                        process(i, &mut out);
+                    }
+                    out
+                })
+            }).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        chunks.into_iter().flatten().collect()
+    }
+
+    /// Draws screen-space triangles into the buffer in the slice order.
+    /// `draw` renders one triangle into the region of a tile; whatever it draws must stay
+    /// within `margin` pixels of the triangle bounds (e.g. thick wireframe lines).
+    pub fn rasterize<F>(&self, buffer: &mut FrameBuffer, tris: &[Triangle], margin: i64, draw: F)
+        where F: Fn(&mut Region, &Triangle) + Sync {
+        let size = self.tile_size as i64;
+        let width = buffer.target_width() as i64;
+        let height = buffer.target_height() as i64;
+        let samples = buffer.samples();
+        let tiles_x = (width + size - 1) / size;
+        let tiles_y = (height + size - 1) / size;
+
+        // Binning: every tile gets the indices of the triangles overlapping it, in order
+        let mut bins: Vec<Vec<usize>> = vec![Vec::new(); (tiles_x * tiles_y) as usize];
+        for (i, tri) in tris.iter().enumerate() {
+            let bounds = raster::pixel_bounds(tri);
+            if bounds[2] + margin < 0 || bounds[3] + margin < 0 {
+                continue
+            }
+            let min_tx = (bounds[0] - margin).max(0) / size;
+            let min_ty = (bounds[1] - margin).max(0) / size;
+            let max_tx = (bounds[2] + margin).min(width - 1) / size;
+            let max_ty = (bounds[3] + margin).min(height - 1) / size;
+            for ty in min_ty..=max_ty {
+                for tx in min_tx..=max_tx {
+                    bins[(ty * tiles_x + tx) as usize].push(i);
+                }
+            }
+        }
+
+        // A row of tiles shares a contiguous band of the buffer, bands are dealt to the workers
+        let workers = self.workers(tris.len());
+        let band_size = (width * size) as usize * samples;
+        let mut work: Vec<Vec<Band>> = (0..workers).map(|_| Vec::new()).collect();
+        let bands = buffer.pixels.chunks_mut(band_size).zip(buffer.depth.chunks_mut(band_size));
+        for (ty, (band, depth)) in bands.enumerate() {
+            work[ty % workers].push((ty as i64, band, depth));
+        }
+
+        let bins = &bins;
+        let draw = &draw;
+        let run = move |bands: Vec<Band>| {
+            for (ty, band, depth) in bands {
+                for tx in 0..tiles_x {
+                    let clip = [tx * size, ty * size,
+                                ((tx + 1) * size).min(width) - 1, ((ty + 1) * size).min(height) - 1];
+                    let mut region = Region {
+                        pixels: &mut *band, depth: &mut *depth, width, first_row: ty * size, clip, samples
+                    };
+                    for &i in &bins[(ty * tiles_x + tx) as usize] {
+                        draw(&mut region, &tris[i]);
+                    }
+                }
+            }
+        };
+        if workers == 1 {
+            work.into_iter().for_each(run);
+            return
+        }
+        thread::scope(|s| {
+            for bands in work {
+                s.spawn(move || run(bands));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::pixels::Color;
+    use crate::naive::gfx::Vec3D;
+    use crate::naive::gfx::shading::BlendMode;
+    use crate::naive::raster::{AntiAliasing, RenderMode, WireStyle};
+
+    /// Overlapping, partly transparent triangles all over a 200x150 frame and a bit beyond it
+    fn triangles() -> Vec<Triangle> {
+        let mut seed = 12345u32;
+        let mut next = |range: f32| {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (seed >> 8) as f32 / (1 << 24) as f32 * range
+        };
+        (0..500).map(|_| {
+            let (x, y) = (next(240.0) - 20.0, next(190.0) - 20.0);
+            let mut point = || Vec3D::new(x + next(60.0) - 30.0, y + next(60.0) - 30.0, next(1.0));
+            let (a, b, c) = (point(), point(), point());
+            let color = Color::RGBA(next(255.0) as u8, next(255.0) as u8, next(255.0) as u8, 128 + next(127.0) as u8);
+            Triangle::new(a, b, c, Some(color))
+        }).collect()
+    }
+
+    fn render(tiles: Option<&TileRenderer>) -> FrameBuffer {
+        let mut buffer = FrameBuffer::with_anti_aliasing(200, 150, AntiAliasing::Msaa(4));
+        buffer.clear(Color::RGBA(0, 0, 0, 255));
+        let style = WireStyle { color: Color::RGB(0, 200, 255), thickness: 2, background: Color::RGB(0, 0, 0) };
+        let draw = |region: &mut Region, tri: &Triangle| {
+            region.draw_triangle(tri, RenderMode::SolidWireframe, BlendMode::Alpha, &style, |_| tri.color)
+        };
+        let tris = triangles();
+        match tiles {
+            Some(tiles) => tiles.rasterize(&mut buffer, &tris, style.thickness as i64 + 1, draw),
+            None => {
+                let mut region = buffer.region();
+                for tri in tris.iter() {
+                    draw(&mut region, tri);
+                }
+            },
+        }
+        buffer
+    }
+
+    #[test]
+    fn tiled_output_matches_the_single_threaded_path() {
+        let expected = render(None);
+        // small odd tiles on more threads than bands, then the inline path of small jobs
+        let threaded = TileRenderer { tile_size: 24, threads: 8, min_chunk: 1 };
+        let inline = TileRenderer { tile_size: 64, threads: 8, min_chunk: 10_000 };
+        for tiles in [threaded, inline] {
+            let buffer = render(Some(&tiles));
+            assert!(buffer.pixels == expected.pixels, "tiles of {} differ", tiles.tile_size);
+            assert!(buffer.depth == expected.depth, "tiles of {} differ", tiles.tile_size);
+        }
+    }
+
+    #[test]
+    fn processing_keeps_the_item_order() {
+        let tiles = TileRenderer { tile_size: 64, threads: 8, min_chunk: 3 };
+        let items: Vec<usize> = (0..100).collect();
+        let out = tiles.process(&items, |i, &item, out| out.extend([i, item]));
+        let expected: Vec<usize> = (0..100).flat_map(|i| [i, i]).collect();
+        assert_eq!(out, expected);
+    }
+}